@@ -476,7 +476,7 @@ main() {
         ls -la certs/
         echo ""
         echo "📋 Usage instructions:"
-        echo "  🔧 Regenerate certificate: bin/cert-gen"
+        echo "  🔧 Regenerate certificate: bin/cert-gen --force"
         echo "  🚀 Start server:   bin/yt-mp3"
         echo "  🌐 HTTP:         http://127.0.0.1:3000"
         echo "  🔒 HTTPS:        https://127.0.0.1:3443"
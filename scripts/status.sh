@@ -141,6 +141,40 @@ test_service_response() {
     return $( [ "$all_tests_ok" = true ] && echo 0 || echo 1 )
 }
 
+# 檢查就緒狀態 (/readyz)
+check_readiness() {
+    print_step "檢查服務就緒狀態..."
+    
+    if ! command -v curl > /dev/null 2>&1; then
+        print_warning "無法檢查就緒狀態 (curl 未安裝)"
+        return 0
+    fi
+    
+    local body
+    local response_code
+    body=$(curl -s --connect-timeout 5 -w "\n%{http_code}" "http://127.0.0.1:3000/readyz" 2>/dev/null || echo "000")
+    response_code=$(echo "$body" | tail -1)
+    body=$(echo "$body" | sed '$d')
+    
+    if [ "$response_code" = "200" ]; then
+        print_success "服務已就緒 (HTTP $response_code)"
+    elif [ "$response_code" = "503" ]; then
+        print_warning "服務未就緒 (HTTP $response_code)"
+    else
+        print_error "無法取得就緒狀態"
+        return 1
+    fi
+    
+    # 顯示各項檢查結果
+    if command -v jq > /dev/null 2>&1; then
+        echo "$body" | jq -r '.checks | to_entries[] | "  \(if .value.ok then "✅" else "❌" end) \(.key) \(.value.version // .value.error // "")"'
+    else
+        echo "  $body"
+    fi
+    
+    return $( [ "$response_code" = "200" ] && echo 0 || echo 1 )
+}
+
 # 檢查系統資源使用
 check_system_resources() {
     print_step "檢查系統資源使用..."
@@ -347,6 +381,8 @@ main() {
         show_service_overview
         
         if [ "$detailed" = true ]; then
+            echo ""
+            check_readiness || true
             echo ""
             check_system_resources
             echo ""
@@ -113,9 +113,9 @@ stop_service() {
     print_info "嘗試優雅關閉服務..."
     kill -TERM "$pid" 2>/dev/null || true
     
-    # 等待進程結束
+    # 等待進程結束 (服務會先等待進行中的轉換完成)
     local wait_time=0
-    local max_wait=10
+    local max_wait=$(( ${YT_MP3_SHUTDOWN_TIMEOUT_SECS:-30} + 15 ))
     while [ $wait_time -lt $max_wait ]; do
         if ! ps -p "$pid" > /dev/null 2>&1; then
             print_success "服務已優雅關閉"
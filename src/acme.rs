@@ -0,0 +1,303 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, OrderStatus, RetryPolicy,
+};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    Certificate, PrivateKey,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    net::IpAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use yt_mp3_service::certs;
+
+use crate::{
+    config::{AcmeChallenge, Config},
+    tls::{self, CertInfo},
+    AppState,
+};
+
+// ALPN protocol the ACME server offers when validating TLS-ALPN-01 (RFC 8737)
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+// How often the certificate is checked against the ACME configuration
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+// Delay before ordering again after a failed attempt
+const RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+// Challenge responses published while an order is being validated
+#[derive(Default)]
+pub struct Challenges {
+    // HTTP-01: token -> key authorization
+    http: RwLock<HashMap<String, String>>,
+    // TLS-ALPN-01: domain -> validation certificate
+    tls_alpn: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl Challenges {
+    fn clear(&self) {
+        self.http.write().unwrap().clear();
+        self.tls_alpn.write().unwrap().clear();
+    }
+}
+
+// Answer HTTP-01 validation requests from the ACME server
+pub async fn http_challenge(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    match state.acme.http.read().unwrap().get(&token) {
+        Some(key_authorization) => key_authorization.clone().into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// Serves the configured certificate, or the TLS-ALPN-01 validation
+// certificate when the ACME server connects with `acme-tls/1`
+pub struct CertResolver {
+    certified_key: Arc<CertifiedKey>,
+    challenges: Arc<Challenges>,
+}
+
+impl CertResolver {
+    pub fn new(
+        chain: Vec<Certificate>,
+        key: &PrivateKey,
+        challenges: Arc<Challenges>,
+    ) -> Result<Self, String> {
+        let signing_key = rustls::sign::any_supported_type(key)
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        Ok(CertResolver {
+            certified_key: Arc::new(CertifiedKey::new(chain, signing_key)),
+            challenges,
+        })
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let validating = client_hello
+            .alpn()
+            .is_some_and(|mut protocols| protocols.any(|protocol| protocol == ACME_TLS_ALPN));
+        if !validating {
+            return Some(self.certified_key.clone());
+        }
+
+        let name = client_hello.server_name()?;
+        self.challenges.tls_alpn.read().unwrap().get(name).cloned()
+    }
+}
+
+// Keep the HTTPS listener supplied with a certificate from the ACME server
+//
+// A certificate is ordered when the current one does not cover the configured
+// domains, was issued by the local CA, or is within the renewal window.
+pub async fn run(
+    tls_config: RustlsConfig,
+    cert: Arc<RwLock<Option<CertInfo>>>,
+    config: Arc<Config>,
+    challenges: Arc<Challenges>,
+) {
+    loop {
+        let delay = match renewal_reason(&config, &cert) {
+            None => CHECK_INTERVAL,
+            Some(reason) => {
+                println!("🔄 Ordering certificate from the ACME server: {}", reason);
+                let result = order_certificate(&config, &challenges).await;
+                challenges.clear();
+                match result.and_then(|_| tls::reload(&tls_config, &cert, &config, &challenges)) {
+                    Ok(_) => CHECK_INTERVAL,
+                    Err(e) => {
+                        eprintln!(
+                            "⚠️  ACME certificate order failed, retrying in {} minutes: {}",
+                            RETRY_DELAY.as_secs() / 60,
+                            e
+                        );
+                        RETRY_DELAY
+                    }
+                }
+            }
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Why the certificate on disk should be replaced, None when it is fine
+fn renewal_reason(config: &Config, cert: &RwLock<Option<CertInfo>>) -> Option<String> {
+    let leaf = match tls::read_cert_chain(&config.cert_path) {
+        Ok(chain) => chain.into_iter().next()?,
+        Err(e) => return Some(e),
+    };
+
+    let sans = certs::sans_of(&leaf.0);
+    if let Some(missing) = config
+        .acme_domains
+        .iter()
+        .find(|domain| !sans.contains(&certs::parse_san(domain)))
+    {
+        return Some(format!("certificate does not cover {}", missing));
+    }
+    if certs::is_locally_issued(&leaf.0, &tls::ca_dir(config)) {
+        return Some("certificate was issued by the local CA".to_string());
+    }
+    let days_remaining = cert.read().unwrap().as_ref()?.days_remaining();
+    if days_remaining < config.cert_renew_days {
+        return Some(format!("certificate expires in {} days", days_remaining));
+    }
+    None
+}
+
+// Run one order through validation and write the issued chain and key
+async fn order_certificate(config: &Config, challenges: &Challenges) -> Result<(), String> {
+    let account = load_account(config).await?;
+
+    let identifiers: Vec<Identifier> = config
+        .acme_domains
+        .iter()
+        .map(|domain| match domain.parse::<IpAddr>() {
+            Ok(ip) => Identifier::Ip(ip),
+            Err(_) => Identifier::Dns(domain.clone()),
+        })
+        .collect();
+    let mut order = account
+        .new_order(&NewOrder::new(&identifiers))
+        .await
+        .map_err(|e| format!("Cannot create order: {}", e))?;
+
+    let challenge_type = match config.acme_challenge {
+        AcmeChallenge::Http01 => ChallengeType::Http01,
+        AcmeChallenge::TlsAlpn01 => ChallengeType::TlsAlpn01,
+    };
+    let mut authorizations = order.authorizations();
+    while let Some(result) = authorizations.next().await {
+        let mut authorization = result.map_err(|e| format!("Cannot fetch authorization: {}", e))?;
+        match authorization.status {
+            AuthorizationStatus::Pending => {}
+            AuthorizationStatus::Valid => continue,
+            status => return Err(format!("Authorization is {:?}", status)),
+        }
+
+        let mut challenge = authorization
+            .challenge(challenge_type.clone())
+            .ok_or_else(|| format!("ACME server offers no {:?} challenge", challenge_type))?;
+        let key_authorization = challenge.key_authorization();
+        match config.acme_challenge {
+            AcmeChallenge::Http01 => {
+                challenges
+                    .http
+                    .write()
+                    .unwrap()
+                    .insert(challenge.token.clone(), key_authorization.as_str().to_string());
+            }
+            AcmeChallenge::TlsAlpn01 => {
+                let domain = challenge.identifier().to_string();
+                let validation = validation_certificate(&domain, key_authorization.digest().as_ref())?;
+                challenges.tls_alpn.write().unwrap().insert(domain, validation);
+            }
+        }
+        challenge
+            .set_ready()
+            .await
+            .map_err(|e| format!("Cannot start validation: {}", e))?;
+    }
+
+    let status = order
+        .poll_ready(&RetryPolicy::default())
+        .await
+        .map_err(|e| format!("Validation failed: {}", e))?;
+    if status != OrderStatus::Ready {
+        return Err(format!("Order is {:?} after validation", status));
+    }
+
+    // The key stays local, the ACME server only sees the signing request
+    let mut params = rcgen::CertificateParams::default();
+    params.subject_alt_names = config
+        .acme_domains
+        .iter()
+        .map(|domain| certs::parse_san(domain))
+        .collect();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let request = rcgen::Certificate::from_params(params).map_err(|e| e.to_string())?;
+    let csr = request.serialize_request_der().map_err(|e| e.to_string())?;
+    order
+        .finalize_csr(&csr)
+        .await
+        .map_err(|e| format!("Cannot finalize order: {}", e))?;
+    let chain = order
+        .poll_certificate(&RetryPolicy::default())
+        .await
+        .map_err(|e| format!("Cannot download certificate: {}", e))?;
+
+    certs::write_private(&config.key_path, request.serialize_private_key_pem())
+        .map_err(|e| format!("Cannot write {}: {}", config.key_path.display(), e))?;
+    fs::write(&config.cert_path, chain)
+        .map_err(|e| format!("Cannot write {}: {}", config.cert_path.display(), e))?;
+    println!("✅ Certificate issued by the ACME server for {}", config.acme_domains.join(", "));
+    Ok(())
+}
+
+// Restore the ACME account from disk, registering a new one on first use
+async fn load_account(config: &Config) -> Result<Account, String> {
+    let directory = config.acme_directory.clone().unwrap_or_default();
+    let builder = match &config.acme_ca_root {
+        Some(root) => Account::builder_with_root(root),
+        None => Account::builder(),
+    }
+    .map_err(|e| format!("Cannot create ACME client: {}", e))?;
+
+    let path = &config.acme_account_path;
+    if path.exists() {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let credentials: AccountCredentials = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid account file {}: {}", path.display(), e))?;
+        return builder
+            .from_credentials(credentials)
+            .await
+            .map_err(|e| format!("Cannot restore ACME account: {}", e));
+    }
+
+    let contact: Vec<&str> = config.acme_contact.iter().map(String::as_str).collect();
+    let (account, credentials) = builder
+        .create(
+            &NewAccount {
+                contact: &contact,
+                terms_of_service_agreed: true,
+                only_return_existing: false,
+            },
+            directory,
+            None,
+        )
+        .await
+        .map_err(|e| format!("Cannot register ACME account: {}", e))?;
+
+    // The credentials include the account key
+    let json = serde_json::to_string_pretty(&credentials).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+    certs::write_private(path, &json)
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    println!("🔑 Registered ACME account, credentials saved to {}", path.display());
+    Ok(account)
+}
+
+// Self-signed certificate carrying the acmeIdentifier extension (RFC 8737)
+fn validation_certificate(domain: &str, digest: &[u8]) -> Result<Arc<CertifiedKey>, String> {
+    let mut params = rcgen::CertificateParams::new(vec![domain.to_string()]);
+    params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(digest)];
+    let cert = rcgen::Certificate::from_params(params).map_err(|e| e.to_string())?;
+    let der = cert.serialize_der().map_err(|e| e.to_string())?;
+    let key = PrivateKey(cert.serialize_private_key_der());
+    let signing_key = rustls::sign::any_supported_type(&key).map_err(|e| e.to_string())?;
+    Ok(Arc::new(CertifiedKey::new(vec![Certificate(der)], signing_key)))
+}
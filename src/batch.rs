@@ -0,0 +1,451 @@
+use axum::{
+    extract::{multipart::Field, FromRequest, Multipart, Path, Request, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    Form, Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::{
+    client_auth::ClientIdentity,
+    credentials,
+    feeds::BaseUrl,
+    history::task_json,
+    parse_callback_url, parse_collection, start_conversion, subtitles,
+    tasks::{unix_now, Batch, ConvertOptions, TaskRecord, TaskStatus},
+    validate_youtube_url, AppState,
+};
+
+// JSON body: a bare array of URLs, or an object with options
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BatchJson {
+    Urls(Vec<String>),
+    Request {
+        urls: UrlList,
+        #[serde(default)]
+        callback_url: Option<String>,
+        #[serde(default)]
+        split_chapters: bool,
+        #[serde(default)]
+        normalize: bool,
+        #[serde(default)]
+        square_thumbnail: bool,
+        #[serde(default)]
+        collection: Option<String>,
+        #[serde(default)]
+        subtitles: Option<String>,
+        #[serde(default)]
+        auto_subtitles: bool,
+        #[serde(default)]
+        embed_lyrics: bool,
+        #[serde(default)]
+        proxy: Option<String>,
+    },
+}
+
+// URLs as an array or as text with one URL per line
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UrlList {
+    Array(Vec<String>),
+    Text(String),
+}
+
+// Form body, `urls` holds the textarea contents
+#[derive(Deserialize)]
+struct BatchForm {
+    #[serde(default)]
+    urls: String,
+    #[serde(default)]
+    callback_url: Option<String>,
+    #[serde(default)]
+    split_chapters: bool,
+    #[serde(default)]
+    normalize: bool,
+    #[serde(default)]
+    square_thumbnail: bool,
+    #[serde(default)]
+    collection: Option<String>,
+    #[serde(default)]
+    subtitles: Option<String>,
+    #[serde(default)]
+    auto_subtitles: bool,
+    #[serde(default)]
+    embed_lyrics: bool,
+    #[serde(default)]
+    proxy: Option<String>,
+}
+
+// Entries of a submission in the order given, before validation
+#[derive(Default)]
+struct Submission {
+    entries: Vec<String>,
+    callback_url: Option<String>,
+    // Applied to every task of the batch
+    options: ConvertOptions,
+    collection: Option<String>,
+}
+
+// POST /batches
+//
+// Every URL is validated on its own: valid ones become tasks of the new
+// batch, the others are reported by their position in the submission.
+pub async fn create_batch(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    base_url: Option<BaseUrl>,
+    request: Request,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let bad_request = |body: Value| (StatusCode::BAD_REQUEST, Json(body));
+
+    // Refuse new work while draining for shutdown
+    if state.shutdown.is_draining() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "Server is shutting down, please try again later" })),
+        ));
+    }
+
+    let submission = read_submission(request)
+        .await
+        .map_err(|error| bad_request(json!({ "error": error })))?;
+    let callback_url = parse_callback_url(submission.callback_url, &state.config)
+        .await
+        .map_err(|error| bad_request(json!({ "error": error })))?;
+    let collection = parse_collection(submission.collection)
+        .map_err(|error| bad_request(json!({ "error": error })))?;
+    if submission.entries.is_empty() {
+        return Err(bad_request(json!({ "error": "No URLs provided" })));
+    }
+    let limit = state.config.max_batch_size;
+    if submission.entries.len() > limit {
+        return Err(bad_request(json!({
+            "error": format!("Too many URLs: {} (limit {})", submission.entries.len(), limit)
+        })));
+    }
+
+    let mut accepted = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, entry) in submission.entries.into_iter().enumerate() {
+        let error = match validate_youtube_url(&entry) {
+            Err(error) => Some(error),
+            Ok(()) if !seen.insert(entry.clone()) => {
+                Some("Duplicate URL in this batch".to_string())
+            }
+            Ok(()) => None,
+        };
+        match error {
+            Some(error) => errors.push(json!({ "index": index, "input": entry, "error": error })),
+            None => accepted.push((index, entry)),
+        }
+    }
+    if accepted.is_empty() {
+        return Err(bad_request(
+            json!({ "error": "No valid URLs", "errors": errors }),
+        ));
+    }
+
+    let base_url = base_url.map(|BaseUrl(base)| base);
+    let mut tasks = Vec::new();
+    let mut task_ids = Vec::new();
+    for (index, url) in accepted {
+        let record = TaskRecord {
+            options: submission.options.clone(),
+            collection: collection.clone(),
+            base_url: base_url.clone(),
+            ..TaskRecord::new(Some(url.clone()), identity.0.clone(), callback_url.clone())
+        };
+        let task_id = start_conversion(&state, record);
+        tasks.push(json!({ "index": index, "url": url, "task_id": task_id }));
+        task_ids.push(task_id);
+    }
+
+    let batch_id = Uuid::new_v4().to_string();
+    state.tasks.create_batch(
+        &batch_id,
+        Batch {
+            task_ids,
+            created_at: unix_now(),
+            owner: identity.0,
+        },
+    );
+
+    Ok(Json(json!({
+        "batch_id": batch_id,
+        "status": "processing",
+        "tasks": tasks,
+        "errors": errors,
+    })))
+}
+
+// GET /batches/:batch_id, with the state of every task
+pub async fn batch_status(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(batch_id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let batch = state
+        .tasks
+        .batch(&batch_id)
+        .filter(|batch| identity.may_access(batch.owner.as_deref()))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "status": "not_found", "error": "Batch not found" })),
+            )
+        })?;
+
+    // Tasks deleted from the history no longer count
+    let (mut processing, mut completed, mut failed) = (0usize, 0usize, 0usize);
+    let mut tasks = Vec::new();
+    for task_id in &batch.task_ids {
+        let Some(record) = state.tasks.get(task_id) else {
+            continue;
+        };
+        match record.status {
+            TaskStatus::Processing => processing += 1,
+            TaskStatus::Completed(..) => completed += 1,
+            TaskStatus::Failed(_) => failed += 1,
+        }
+        tasks.push(task_json(task_id, &record));
+    }
+
+    let total = tasks.len();
+    let status = match (processing, completed, failed) {
+        (0, _, 0) => "completed",
+        (0, 0, _) => "failed",
+        (0, _, _) => "partial",
+        _ => "processing",
+    };
+    let progress = ((completed + failed) * 100)
+        .checked_div(total)
+        .unwrap_or(100);
+    Ok(Json(json!({
+        "batch_id": batch_id,
+        "status": status,
+        "created_at": batch.created_at,
+        "total": total,
+        "processing": processing,
+        "completed": completed,
+        "failed": failed,
+        "progress": progress,
+        "tasks": tasks,
+    })))
+}
+
+// Collect the URLs from a JSON, multipart or urlencoded body
+async fn read_submission(request: Request) -> Result<Submission, String> {
+    let content_type = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    if content_type.starts_with("application/json") {
+        let Json(body) = Json::<BatchJson>::from_request(request, &())
+            .await
+            .map_err(|e| e.body_text())?;
+        return Ok(match body {
+            BatchJson::Urls(urls) => Submission {
+                entries: trimmed(urls),
+                ..Submission::default()
+            },
+            BatchJson::Request {
+                urls,
+                callback_url,
+                split_chapters,
+                normalize,
+                square_thumbnail,
+                collection,
+                subtitles,
+                auto_subtitles,
+                embed_lyrics,
+                proxy,
+            } => Submission {
+                entries: match urls {
+                    UrlList::Array(urls) => trimmed(urls),
+                    UrlList::Text(text) => lines(&text),
+                },
+                callback_url,
+                options: ConvertOptions {
+                    split_chapters,
+                    normalize,
+                    square_thumbnail,
+                    subtitles: subtitles::parse_languages(subtitles)?,
+                    auto_subtitles,
+                    embed_lyrics,
+                    proxy: credentials::parse_proxy(proxy)?,
+                    format: None,
+                },
+                collection,
+            },
+        });
+    }
+
+    if content_type.starts_with("multipart/form-data") {
+        let mut multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|e| e.body_text())?;
+        let mut submission = Submission::default();
+        while let Some(field) = multipart.next_field().await.map_err(|e| e.body_text())? {
+            match field.name() {
+                Some("urls") => {
+                    let text = field.text().await.map_err(|e| e.body_text())?;
+                    submission.entries.extend(lines(&text));
+                }
+                Some("file") => {
+                    let csv = field.content_type() == Some("text/csv")
+                        || field
+                            .file_name()
+                            .is_some_and(|name| name.to_ascii_lowercase().ends_with(".csv"));
+                    let text = field.text().await.map_err(|e| e.body_text())?;
+                    submission.entries.extend(if csv {
+                        csv_entries(&text)
+                    } else {
+                        lines(&text)
+                    });
+                }
+                Some("split_chapters") => {
+                    submission.options.split_chapters = checkbox(field).await?
+                }
+                Some("normalize") => submission.options.normalize = checkbox(field).await?,
+                Some("square_thumbnail") => {
+                    submission.options.square_thumbnail = checkbox(field).await?
+                }
+                Some("subtitles") => {
+                    let text = field.text().await.map_err(|e| e.body_text())?;
+                    submission.options.subtitles = subtitles::parse_languages(Some(text))?;
+                }
+                Some("auto_subtitles") => {
+                    submission.options.auto_subtitles = checkbox(field).await?
+                }
+                Some("embed_lyrics") => submission.options.embed_lyrics = checkbox(field).await?,
+                Some("proxy") => {
+                    let text = field.text().await.map_err(|e| e.body_text())?;
+                    submission.options.proxy = credentials::parse_proxy(Some(text))?;
+                }
+                Some("callback_url") => {
+                    submission.callback_url = Some(field.text().await.map_err(|e| e.body_text())?);
+                }
+                Some("collection") => {
+                    submission.collection = Some(field.text().await.map_err(|e| e.body_text())?);
+                }
+                _ => {}
+            }
+        }
+        return Ok(submission);
+    }
+
+    let Form(form) = Form::<BatchForm>::from_request(request, &())
+        .await
+        .map_err(|e| e.body_text())?;
+    Ok(Submission {
+        entries: lines(&form.urls),
+        callback_url: form.callback_url,
+        options: ConvertOptions {
+            split_chapters: form.split_chapters,
+            normalize: form.normalize,
+            square_thumbnail: form.square_thumbnail,
+            subtitles: subtitles::parse_languages(form.subtitles)?,
+            auto_subtitles: form.auto_subtitles,
+            embed_lyrics: form.embed_lyrics,
+            proxy: credentials::parse_proxy(form.proxy)?,
+            format: None,
+        },
+        collection: form.collection,
+    })
+}
+
+// Whether a checkbox field was ticked
+async fn checkbox(field: Field<'_>) -> Result<bool, String> {
+    let value = field.text().await.map_err(|e| e.body_text())?;
+    Ok(matches!(value.trim(), "true" | "on" | "1"))
+}
+
+fn trimmed(urls: Vec<String>) -> Vec<String> {
+    urls.into_iter()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+// One entry per non-empty line; `#` starts a comment line
+fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+// The URL cell of every CSV row
+//
+// A first row without a URL is taken as the header; later rows without one
+// are kept whole so they are reported as invalid.
+fn csv_entries(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .filter_map(|(row, line)| {
+            let url = line
+                .split([',', ';', '\t'])
+                .map(|cell| cell.trim().trim_matches('"').trim())
+                .find(|cell| cell.contains("://"));
+            match url {
+                Some(url) => Some(url.to_string()),
+                None if row == 0 => None,
+                None => Some(line.to_string()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_skip_blanks_and_comments() {
+        let text = "# favourites\n  https://youtu.be/a  \n\n\r\nhttps://youtu.be/b\r\n   # later\n";
+        assert_eq!(lines(text), ["https://youtu.be/a", "https://youtu.be/b"]);
+    }
+
+    #[test]
+    fn trimmed_drops_empty_urls() {
+        let urls = vec![
+            " https://youtu.be/a ".to_string(),
+            "   ".to_string(),
+            String::new(),
+        ];
+        assert_eq!(trimmed(urls), ["https://youtu.be/a"]);
+    }
+
+    #[test]
+    fn csv_entries_take_the_url_cell() {
+        let text = "title,url\nSong,https://youtu.be/a\n\"Other\";\"https://youtu.be/b\"\nx\thttps://youtu.be/c\t1\n";
+        assert_eq!(
+            csv_entries(text),
+            [
+                "https://youtu.be/a",
+                "https://youtu.be/b",
+                "https://youtu.be/c"
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_entries_keep_later_rows_without_url() {
+        // Without a header the first row is a URL like any other
+        assert_eq!(
+            csv_entries("https://youtu.be/a\nno url here\n\n"),
+            ["https://youtu.be/a", "no url here"]
+        );
+        assert_eq!(csv_entries("title,url\n"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,490 @@
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SanType,
+};
+use rsa::pkcs8::EncodePrivateKey;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use time::{Duration, OffsetDateTime};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use crate::lan::{self, InterfaceFilter};
+
+pub type CertResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// Local root CA, created once and reused for every leaf certificate
+pub const CA_CERT_FILE: &str = "ca.pem";
+pub const CA_CERT_DER_FILE: &str = "ca.der";
+pub const CA_KEY_FILE: &str = "ca-key.pem";
+pub const CA_VALIDITY_DAYS: i64 = 3650;
+
+// Validity of server certificates when not specified
+pub const DEFAULT_LEAF_DAYS: i64 = 90;
+
+// Validity of client certificates when not specified
+pub const DEFAULT_CLIENT_DAYS: i64 = 365;
+
+// Organization of the self-signed certificates cert-gen wrote before the CA
+const LEGACY_ORGANIZATION: &str = "YT-MP3-Service";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    P256,
+    P384,
+    Ed25519,
+    Rsa2048,
+    Rsa4096,
+}
+
+impl KeyType {
+    pub fn generate(self) -> CertResult<KeyPair> {
+        let key_pair = match self {
+            KeyType::P256 => KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?,
+            KeyType::P384 => KeyPair::generate(&rcgen::PKCS_ECDSA_P384_SHA384)?,
+            KeyType::Ed25519 => KeyPair::generate(&rcgen::PKCS_ED25519)?,
+            // ring cannot generate RSA keys, so create one and import it
+            KeyType::Rsa2048 | KeyType::Rsa4096 => {
+                let bits = if self == KeyType::Rsa2048 { 2048 } else { 4096 };
+                let key = rsa::RsaPrivateKey::new(&mut rand::rngs::OsRng, bits)?;
+                let der = key.to_pkcs8_der()?;
+                KeyPair::from_der_and_sign_algo(der.as_bytes(), &rcgen::PKCS_RSA_SHA256)?
+            }
+        };
+        Ok(key_pair)
+    }
+
+    pub fn is_rsa(self) -> bool {
+        matches!(self, KeyType::Rsa2048 | KeyType::Rsa4096)
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "p256" => Ok(KeyType::P256),
+            "p384" => Ok(KeyType::P384),
+            "ed25519" => Ok(KeyType::Ed25519),
+            "rsa2048" => Ok(KeyType::Rsa2048),
+            "rsa4096" => Ok(KeyType::Rsa4096),
+            other => Err(format!(
+                "unknown key type '{}' (expected p256, p384, ed25519, rsa2048 or rsa4096)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KeyType::P256 => "p256",
+            KeyType::P384 => "p384",
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Rsa2048 => "rsa2048",
+            KeyType::Rsa4096 => "rsa4096",
+        };
+        f.write_str(name)
+    }
+}
+
+// Subject fields of an issued certificate
+#[derive(Debug, Clone)]
+pub struct Subject {
+    // Defaults to the first DNS name among the SANs
+    pub common_name: Option<String>,
+    pub organization: String,
+    pub organizational_unit: Option<String>,
+    pub locality: Option<String>,
+    pub state: Option<String>,
+    pub country: String,
+}
+
+impl Default for Subject {
+    fn default() -> Self {
+        Subject {
+            common_name: None,
+            organization: "YT-MP3-Service".to_string(),
+            organizational_unit: None,
+            locality: None,
+            state: None,
+            country: "TW".to_string(),
+        }
+    }
+}
+
+// What to put in a server certificate
+#[derive(Debug, Clone)]
+pub struct LeafOptions {
+    pub sans: Vec<SanType>,
+    pub days: i64,
+    pub key_type: KeyType,
+    pub subject: Subject,
+}
+
+// What to put in a client certificate
+//
+// The subject common name identifies the user; email addresses are added
+// as subject alternative names.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub emails: Vec<String>,
+    pub days: i64,
+    pub key_type: KeyType,
+    pub subject: Subject,
+}
+
+// The local root CA and where its certificate lives
+pub struct LocalCa {
+    cert: Certificate,
+    pub cert_path: PathBuf,
+    pub der_path: PathBuf,
+    // True when this call created the CA
+    pub created: bool,
+}
+
+impl LocalCa {
+    // Load the local CA from disk, or create it on first run
+    //
+    // Only the CA key and subject are needed for signing, so the existing CA
+    // certificate on disk is never rewritten and stays valid as a trust anchor.
+    pub fn load_or_create(ca_dir: &Path, key_type: KeyType) -> CertResult<Self> {
+        let cert_path = ca_dir.join(CA_CERT_FILE);
+        let der_path = ca_dir.join(CA_CERT_DER_FILE);
+        let key_path = ca_dir.join(CA_KEY_FILE);
+
+        if cert_path.exists() && key_path.exists() {
+            let ca_pem = fs::read_to_string(&cert_path)?;
+            let key_pair = KeyPair::from_pem(&fs::read_to_string(&key_path)?)?;
+            let params = CertificateParams::from_ca_cert_pem(&ca_pem, key_pair)?;
+            return Ok(LocalCa {
+                cert: Certificate::from_params(params)?,
+                cert_path,
+                der_path,
+                created: false,
+            });
+        }
+
+        if !ca_dir.exists() {
+            fs::create_dir_all(ca_dir)?;
+        }
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name.push(DnType::CommonName, "YT-MP3-Service Local CA");
+        params.distinguished_name.push(DnType::OrganizationName, "YT-MP3-Service");
+        params.distinguished_name.push(DnType::CountryName, "TW");
+
+        let now = OffsetDateTime::now_utc();
+        params.not_before = now;
+        params.not_after = now + Duration::days(CA_VALIDITY_DAYS);
+
+        // The CA only signs leaf certificates, never intermediates
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        let key_pair = key_type.generate()?;
+        params.alg = key_pair.algorithm();
+        params.key_pair = Some(key_pair);
+
+        // The key first: a certificate without its key is never left behind
+        let cert = Certificate::from_params(params)?;
+        write_private(&key_path, cert.serialize_private_key_pem())?;
+        fs::write(&cert_path, cert.serialize_pem()?)?;
+        fs::write(&der_path, cert.serialize_der()?)?;
+
+        Ok(LocalCa {
+            cert,
+            cert_path,
+            der_path,
+            created: true,
+        })
+    }
+
+    // Issue a server certificate signed by the CA, returning PEM certificate and key
+    pub fn issue_leaf(&self, options: &LeafOptions) -> CertResult<(String, String)> {
+        if options.days <= 0 {
+            return Err("certificate validity must be positive".into());
+        }
+
+        let common_name = options.subject.common_name.clone().unwrap_or_else(|| {
+            options
+                .sans
+                .iter()
+                .find_map(|san| match san {
+                    SanType::DnsName(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| "localhost".to_string())
+        });
+
+        // Set certificate properties
+        let mut cert_params = CertificateParams::default();
+        cert_params.distinguished_name = distinguished_name(&options.subject, common_name);
+
+        // Set validity period
+        let now = OffsetDateTime::now_utc();
+        cert_params.not_before = now;
+        cert_params.not_after = now + Duration::days(options.days);
+
+        // Add Subject Alternative Names
+        cert_params.subject_alt_names = options.sans.clone();
+
+        // Server certificate, not a CA
+        cert_params.is_ca = IsCa::ExplicitNoCa;
+        cert_params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        if options.key_type.is_rsa() {
+            cert_params.key_usages.push(KeyUsagePurpose::KeyEncipherment);
+        }
+        cert_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+
+        self.sign(cert_params, options.key_type)
+    }
+
+    // Issue a client certificate for mutual TLS, returning PEM certificate and key
+    pub fn issue_client(&self, options: &ClientOptions) -> CertResult<(String, String)> {
+        if options.days <= 0 {
+            return Err("certificate validity must be positive".into());
+        }
+        let common_name = options
+            .subject
+            .common_name
+            .clone()
+            .ok_or("client certificates need a common name")?;
+
+        let mut cert_params = CertificateParams::default();
+        cert_params.distinguished_name = distinguished_name(&options.subject, common_name);
+
+        let now = OffsetDateTime::now_utc();
+        cert_params.not_before = now;
+        cert_params.not_after = now + Duration::days(options.days);
+
+        cert_params.subject_alt_names = options
+            .emails
+            .iter()
+            .map(|email| SanType::Rfc822Name(email.clone()))
+            .collect();
+
+        cert_params.is_ca = IsCa::ExplicitNoCa;
+        cert_params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        cert_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+
+        self.sign(cert_params, options.key_type)
+    }
+
+    // Bundle a certificate, its key and the CA certificate as PKCS#12 for browsers
+    pub fn pkcs12(&self, cert_pem: &str, key_pem: &str, password: &str, name: &str) -> CertResult<Vec<u8>> {
+        let (_, cert) = x509_parser::pem::parse_x509_pem(cert_pem.as_bytes())?;
+        let key_der = KeyPair::from_pem(key_pem)?.serialize_der();
+        let ca_der = fs::read(&self.der_path)?;
+        let chain = [
+            p12_keystore::Certificate::from_der(&cert.contents)?,
+            p12_keystore::Certificate::from_der(&ca_der)?,
+        ];
+        let key_id = Sha256::digest(&cert.contents);
+        let mut keystore = p12_keystore::KeyStore::new();
+        keystore.add_entry(
+            name,
+            p12_keystore::KeyStoreEntry::PrivateKeyChain(p12_keystore::PrivateKeyChain::new(key_der, key_id, chain)),
+        );
+        // PBES2 with AES-256 and an HMAC-SHA256 MAC, not the legacy RC2/3DES
+        let pfx = keystore
+            .writer(password)
+            .encryption_algorithm(p12_keystore::EncryptionAlgorithm::PbeWithHmacSha256AndAes256)
+            .mac_algorithm(p12_keystore::MacAlgorithm::HmacSha256)
+            .write()?;
+        Ok(pfx)
+    }
+
+    // Generate a key pair for the certificate and sign it with the CA
+    fn sign(&self, mut cert_params: CertificateParams, key_type: KeyType) -> CertResult<(String, String)> {
+        cert_params.use_authority_key_identifier_extension = true;
+
+        // Generate key pair
+        let key_pair = key_type.generate()?;
+        cert_params.alg = key_pair.algorithm();
+        cert_params.key_pair = Some(key_pair);
+
+        // Generate certificate and sign it with the CA
+        let cert = Certificate::from_params(cert_params)?;
+        let cert_pem = cert.serialize_pem_with_signer(&self.cert)?;
+        let key_pem = cert.serialize_private_key_pem();
+
+        Ok((cert_pem, key_pem))
+    }
+}
+
+fn distinguished_name(subject: &Subject, common_name: String) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    name.push(DnType::OrganizationName, subject.organization.as_str());
+    if let Some(unit) = &subject.organizational_unit {
+        name.push(DnType::OrganizationalUnitName, unit.as_str());
+    }
+    if let Some(locality) = &subject.locality {
+        name.push(DnType::LocalityName, locality.as_str());
+    }
+    if let Some(state) = &subject.state {
+        name.push(DnType::StateOrProvinceName, state.as_str());
+    }
+    name.push(DnType::CountryName, subject.country.as_str());
+    name
+}
+
+// Issue a server certificate from the local CA in `ca_dir` and write it out
+pub fn provision(cert_path: &Path, key_path: &Path, ca_dir: &Path, options: &LeafOptions) -> CertResult<LocalCa> {
+    let ca = LocalCa::load_or_create(ca_dir, options.key_type)?;
+    let (cert_pem, key_pem) = ca.issue_leaf(options)?;
+
+    for path in [cert_path, key_path] {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+    }
+    // Key first: a reload triggered between the writes then fails validation
+    // and keeps the old pair instead of serving a mismatched one
+    write_private(key_path, &key_pem)?;
+    fs::write(cert_path, cert_pem)?;
+    Ok(ca)
+}
+
+// Parse a SAN argument as an IP address or DNS name
+pub fn parse_san(value: &str) -> SanType {
+    match value.parse::<IpAddr>() {
+        Ok(ip) => SanType::IpAddress(ip),
+        Err(_) => SanType::DnsName(value.to_ascii_lowercase()),
+    }
+}
+
+// `localhost` and the loopback address
+pub fn loopback_sans() -> Vec<SanType> {
+    vec![
+        SanType::DnsName("localhost".to_string()),
+        SanType::IpAddress(IpAddr::from([127, 0, 0, 1])),
+    ]
+}
+
+// Append the hostname and LAN addresses reachable by other devices
+pub fn add_lan_sans(sans: &mut Vec<SanType>, filter: &InterfaceFilter) {
+    let identity = lan::detect(filter);
+    let detected = identity
+        .hostnames
+        .into_iter()
+        .map(SanType::DnsName)
+        .chain(identity.addresses.into_iter().map(SanType::IpAddress));
+    for san in detected {
+        push_unique(sans, san);
+    }
+}
+
+pub fn push_unique(sans: &mut Vec<SanType>, san: SanType) {
+    if !sans.contains(&san) {
+        sans.push(san);
+    }
+}
+
+pub fn describe_sans(sans: &[SanType]) -> String {
+    sans.iter()
+        .map(|san| match san {
+            SanType::DnsName(name) => name.clone(),
+            SanType::IpAddress(ip) => ip.to_string(),
+            other => format!("{:?}", other),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// DNS and IP SANs of an existing certificate
+pub fn sans_of(cert_der: &[u8]) -> Vec<SanType> {
+    let Ok((_, cert)) = X509Certificate::from_der(cert_der) else {
+        return Vec::new();
+    };
+    let Ok(Some(extension)) = cert.subject_alternative_name() else {
+        return Vec::new();
+    };
+
+    extension
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns) => Some(SanType::DnsName(dns.to_string())),
+            GeneralName::IPAddress(bytes) => match bytes.len() {
+                4 => Some(SanType::IpAddress(IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?))),
+                16 => Some(SanType::IpAddress(IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?))),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+// Whether a certificate was produced by this tool and may be replaced
+//
+// True for certificates signed by the local CA in `ca_dir` and for the
+// self-signed certificates of older cert-gen versions, recognised by their
+// organization; other self-signed certificates were supplied by the user.
+pub fn is_locally_issued(cert_der: &[u8], ca_dir: &Path) -> bool {
+    let Ok((_, cert)) = X509Certificate::from_der(cert_der) else {
+        return false;
+    };
+    if cert.issuer() == cert.subject() {
+        let legacy = cert
+            .subject()
+            .iter_organization()
+            .any(|org| org.as_str() == Ok(LEGACY_ORGANIZATION));
+        return legacy && cert.verify_signature(None).is_ok();
+    }
+
+    let Ok(ca_pem) = fs::read(ca_dir.join(CA_CERT_FILE)) else {
+        return false;
+    };
+    let Ok((_, ca_pem)) = x509_parser::pem::parse_x509_pem(&ca_pem) else {
+        return false;
+    };
+    match ca_pem.parse_x509() {
+        Ok(ca) => {
+            ca.subject() == cert.issuer() && cert.verify_signature(Some(ca.public_key())).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+// SHA-256 fingerprint as colon-separated hex, as shown by browsers
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// Write a private key readable only by the current user
+//
+// New files are created with mode 0600, so the key is never readable by
+// others, even briefly; an existing file is restricted before it is written.
+pub fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents.as_ref())
+}
@@ -0,0 +1,283 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+    process::Stdio,
+};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    config::Config,
+    failures::{Failure, FailureKind},
+    sanitize_filename,
+    subtitles::{self, Subtitle},
+    tasks::{Artifact, ArtifactKind},
+    video_info::{Chapter, VideoInfo},
+};
+
+// Files written by a chapter split
+pub struct Split {
+    // Zip of every track and the playlist, the task's main download
+    pub archive: String,
+    pub artifacts: Vec<Artifact>,
+}
+
+// Cut `<stem>.mp3` in `dir` into one tagged track per chapter of the video, then
+// bundle the tracks and an M3U playlist in `<stem>.zip`
+//
+// Returns None when the video has no chapters. The unsplit file is removed
+// once every track has been written. Each track gets the `lyrics` cues of
+// its chapter.
+pub async fn split(
+    config: &Config,
+    dir: &Path,
+    audio_filename: &str,
+    info: VideoInfo,
+    lyrics: &[Subtitle],
+    abort: &CancellationToken,
+) -> Result<Option<Split>, Failure> {
+    let stem = audio_filename
+        .strip_suffix(".mp3")
+        .unwrap_or(audio_filename);
+
+    let chapters: Vec<Chapter> = info
+        .chapters
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|chapter| chapter.end_time > chapter.start_time)
+        .collect();
+    if chapters.is_empty() {
+        return Ok(None);
+    }
+
+    let album = info.title.unwrap_or_else(|| stem.to_string());
+    let artist = info.artist.or(info.uploader);
+    let total = chapters.len();
+
+    let mut tracks = Vec::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        let number = index + 1;
+        let (title, filename) = track_name(stem, number, total, chapter.title.as_deref());
+
+        let mut command = Command::new(&config.ffmpeg_path);
+        command
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-ss", &format!("{:.3}", chapter.start_time)])
+            .args(["-to", &format!("{:.3}", chapter.end_time)])
+            .arg("-i")
+            .arg(dir.join(audio_filename))
+            .args(["-map", "0:a", "-c", "copy", "-map_metadata", "-1"])
+            .args(["-id3v2_version", "3"])
+            .args(["-metadata", &format!("title={}", title)])
+            .args(["-metadata", &format!("track={}/{}", number, total)])
+            .args(["-metadata", &format!("album={}", album)]);
+        if let Some(artist) = &artist {
+            command
+                .args(["-metadata", &format!("artist={}", artist)])
+                .args(["-metadata", &format!("album_artist={}", artist)]);
+        }
+        command.arg(dir.join(&filename));
+
+        let mut result = run(command, abort).await;
+        if result.is_ok() && !lyrics.is_empty() {
+            let range = (chapter.start_time, chapter.end_time);
+            result = subtitles::embed(dir.join(&filename), lyrics, Some(range))
+                .await
+                .map_err(Failure::from);
+        }
+        tracks.push(Artifact {
+            kind: ArtifactKind::Track,
+            filename,
+            title: Some(title),
+            track: Some(number as u32),
+            duration: Some(chapter.end_time - chapter.start_time),
+            language: None,
+        });
+        if let Err(failure) = result {
+            remove_files(dir, &tracks);
+            return Err(failure);
+        }
+    }
+
+    let playlist = format!("{}.m3u", stem);
+    let archive = format!("{}.zip", stem);
+    let contents = playlist_contents(&tracks);
+    let entries: Vec<String> = tracks.iter().map(|track| track.filename.clone()).collect();
+    let mut artifacts = tracks;
+    for (kind, filename) in [
+        (ArtifactKind::Playlist, &playlist),
+        (ArtifactKind::Archive, &archive),
+    ] {
+        artifacts.push(Artifact {
+            kind,
+            filename: filename.clone(),
+            title: None,
+            track: None,
+            duration: None,
+            language: None,
+        });
+    }
+
+    // Zipping hundreds of megabytes would stall the async workers
+    let archive_path = dir.join(&archive);
+    let bundle_dir = dir.to_path_buf();
+    let written = tokio::task::spawn_blocking(move || {
+        write_bundle(&bundle_dir, &archive_path, &entries, &playlist, &contents)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result.map_err(|e| format!("Cannot write playlist or zip archive: {}", e)));
+    if let Err(e) = written {
+        remove_files(dir, &artifacts);
+        return Err(e.into());
+    }
+
+    let _ = fs::remove_file(dir.join(audio_filename));
+    Ok(Some(Split { archive, artifacts }))
+}
+
+// Title and file name of track `number` of `total`
+//
+// Chapters without a title are named by their number. Numbers are padded to
+// the same width, at least two digits, so the files sort in track order.
+fn track_name(stem: &str, number: usize, total: usize, title: Option<&str>) -> (String, String) {
+    let title = title
+        .filter(|title| !title.trim().is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Chapter {}", number));
+    let width = total.to_string().len().max(2);
+    let filename = format!(
+        "{}_{:0width$}_{}.mp3",
+        stem,
+        number,
+        sanitize_filename(&title),
+        width = width
+    );
+    (title, filename)
+}
+
+// Run ffmpeg, killing it if `abort` fires first
+async fn run(mut command: Command, abort: &CancellationToken) -> Result<(), Failure> {
+    let child = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Cannot execute ffmpeg: {}", e))?;
+    let output = tokio::select! {
+        output = child.wait_with_output() => output.map_err(|e| e.to_string())?,
+        _ = abort.cancelled() => return Err(Failure::new(FailureKind::Cancelled)),
+    };
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!("Cannot split chapter: {}", stderr.trim()).into())
+    }
+}
+
+// Extended M3U with each track's length and title; paths are relative so the
+// playlist works both next to the downloads and inside the zip
+fn playlist_contents(tracks: &[Artifact]) -> String {
+    let mut contents = String::from("#EXTM3U\n");
+    for track in tracks {
+        contents.push_str(&format!(
+            "#EXTINF:{},{}\n{}\n",
+            track.duration.unwrap_or(0.0).round() as u64,
+            track.title.as_deref().unwrap_or_default(),
+            track.filename
+        ));
+    }
+    contents
+}
+
+// Write the playlist, then the zip of the tracks and the playlist
+//
+// MP3s do not compress, so entries are stored as they are.
+fn write_bundle(
+    dir: &Path,
+    archive: &Path,
+    tracks: &[String],
+    playlist: &str,
+    contents: &str,
+) -> io::Result<()> {
+    fs::write(dir.join(playlist), contents)?;
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    let mut zip = ZipWriter::new(File::create(archive)?);
+    for track in tracks {
+        zip.start_file(track.as_str(), options)?;
+        io::copy(&mut File::open(dir.join(track))?, &mut zip)?;
+    }
+    zip.start_file(playlist, options)?;
+    io::Write::write_all(&mut zip, contents.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
+
+fn remove_files(dir: &Path, artifacts: &[Artifact]) {
+    for artifact in artifacts {
+        let _ = fs::remove_file(dir.join(&artifact.filename));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(filename: &str, title: Option<&str>, duration: Option<f64>) -> Artifact {
+        Artifact {
+            kind: ArtifactKind::Track,
+            filename: filename.to_string(),
+            title: title.map(str::to_string),
+            track: None,
+            duration,
+            language: None,
+        }
+    }
+
+    #[test]
+    fn track_name_pads_numbers_and_sanitizes_titles() {
+        assert_eq!(
+            track_name("Album_1", 1, 3, Some("Intro: The/Start")),
+            (
+                "Intro: The/Start".to_string(),
+                "Album_1_01_Intro__The_Start.mp3".to_string()
+            )
+        );
+        assert_eq!(
+            track_name("Album_1", 7, 120, Some("Song")).1,
+            "Album_1_007_Song.mp3"
+        );
+    }
+
+    #[test]
+    fn track_name_numbers_untitled_chapters() {
+        assert_eq!(
+            track_name("Album_1", 3, 3, None),
+            (
+                "Chapter 3".to_string(),
+                "Album_1_03_Chapter_3.mp3".to_string()
+            )
+        );
+        assert_eq!(track_name("Album_1", 2, 3, Some("  ")).0, "Chapter 2");
+    }
+
+    #[test]
+    fn playlist_lists_tracks_with_rounded_lengths() {
+        let tracks = [
+            track("a_01_Intro.mp3", Some("Intro"), Some(61.6)),
+            track("a_02_Chapter_2.mp3", None, None),
+        ];
+        assert_eq!(
+            playlist_contents(&tracks),
+            "#EXTM3U\n\
+             #EXTINF:62,Intro\na_01_Intro.mp3\n\
+             #EXTINF:0,\na_02_Chapter_2.mp3\n"
+        );
+        assert_eq!(playlist_contents(&[]), "#EXTM3U\n");
+    }
+}
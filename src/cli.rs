@@ -0,0 +1,384 @@
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    attempt_conversion,
+    config::Config,
+    credentials,
+    failures::{self, Failure, FailureKind},
+    shutdown, subtitles,
+    tasks::{ArtifactKind, ConvertOptions},
+    validate_youtube_url, Conversion, Progress,
+};
+
+// Exit code of invalid arguments, as clap uses for its own usage errors
+const EXIT_USAGE: i32 = 2;
+// Width of the download progress bar, in characters
+const BAR_WIDTH: usize = 30;
+
+/// YouTube to MP3 converter; runs the web server when no command is given
+#[derive(Parser)]
+#[command(name = "yt-mp3", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert one video on this machine, without starting the server
+    Convert(ConvertArgs),
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// YouTube video or playlist URL
+    url: String,
+
+    /// yt-dlp format selection of the source audio, e.g. `bestaudio[ext=m4a]`
+    /// [default: the best audio]; the result is always an MP3
+    #[arg(long, value_name = "SELECTOR")]
+    format: Option<String>,
+
+    /// Directory the converted files are moved to
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out: PathBuf,
+
+    /// Print the result as JSON instead of the file paths
+    #[arg(long)]
+    json: bool,
+
+    /// One MP3 per chapter, plus a playlist and a zip of all tracks
+    #[arg(long)]
+    split_chapters: bool,
+
+    /// EBU R128 loudness normalization
+    #[arg(long)]
+    normalize: bool,
+
+    /// Crop the thumbnail to a centred square
+    #[arg(long)]
+    square_thumbnail: bool,
+
+    /// Subtitle languages saved as SRT and LRC files, comma-separated
+    #[arg(long, value_name = "LANGS")]
+    subtitles: Option<String>,
+
+    /// Use auto-generated captions where no subtitles exist
+    #[arg(long)]
+    auto_subtitles: bool,
+
+    /// Embed the subtitles in the MP3 as lyrics
+    #[arg(long)]
+    embed_lyrics: bool,
+
+    /// Proxy URL for yt-dlp instead of YT_MP3_PROXY
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+}
+
+// `yt-mp3 convert`: run the conversion pipeline of the server once and move
+// the files to the output directory, returning the process exit code
+//
+// Progress goes to stderr when it is a terminal, so stdout only holds the
+// result: one path per line, or the JSON document with `--json`.
+pub async fn convert(args: ConvertArgs) -> i32 {
+    let json_output = args.json;
+    let url = args.url.clone();
+    let options = match parse_options(args.format, args.subtitles, args.proxy, &url) {
+        Ok(options) => ConvertOptions {
+            split_chapters: args.split_chapters,
+            normalize: args.normalize,
+            square_thumbnail: args.square_thumbnail,
+            auto_subtitles: args.auto_subtitles,
+            embed_lyrics: args.embed_lyrics,
+            ..options
+        },
+        Err(error) => {
+            match json_output {
+                true => println!(
+                    "{}",
+                    json!({ "status": "failed", "url": url, "error": error })
+                ),
+                false => eprintln!("❌ {}", error),
+            }
+            return EXIT_USAGE;
+        }
+    };
+
+    let config = Config::from_env();
+    let options = ConvertOptions {
+        normalize: options.normalize || config.normalize,
+        square_thumbnail: options.square_thumbnail || config.thumbnail_square,
+        ..options
+    };
+    // Files are staged in a directory of this run only, so a server or another
+    // conversion in the same directory never sees or picks up each other's files
+    let staging = std::env::temp_dir().join(format!("yt-mp3-{}", Uuid::new_v4()));
+    if let Err(e) = credentials::create_private_dir(&staging) {
+        eprintln!("❌ Cannot create {}: {}", staging.display(), e);
+        return exit_code(FailureKind::Internal);
+    }
+
+    // Ctrl-C stops yt-dlp and FFmpeg and removes the partial files
+    let abort = CancellationToken::new();
+    let interrupt = abort.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        interrupt.cancel();
+    });
+
+    let started = Instant::now();
+    let (result, attempts) = convert_with_retries(&url, &options, &config, &staging, &abort).await;
+    let result = result.and_then(|conversion| {
+        move_outputs(&conversion, &staging, &args.out)
+            .map(|outputs| (conversion, outputs))
+            .map_err(Failure::from)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    clear_line();
+
+    match result {
+        Ok((conversion, outputs)) => {
+            if json_output {
+                println!(
+                    "{}",
+                    result_json(&url, &conversion, &outputs, attempts, &args.out)
+                );
+            } else {
+                eprintln!("✅ Converted in {:.1}s", started.elapsed().as_secs_f64());
+                println!("{}", outputs.audio.display());
+                for path in outputs
+                    .thumbnail
+                    .iter()
+                    .chain(outputs.artifacts.iter().map(|(_, path)| path))
+                    .filter(|&path| path != &outputs.audio)
+                {
+                    println!("{}", path.display());
+                }
+            }
+            0
+        }
+        Err(mut failure) => {
+            if let Some(detail) = &failure.detail {
+                eprintln!("⚠️  {}", detail);
+            }
+            if failure.kind == FailureKind::Cancelled {
+                failure.message = "Conversion interrupted.".to_string();
+            }
+            let code = exit_code(failure.kind);
+            match json_output {
+                true => println!(
+                    "{}",
+                    json!({
+                        "status": "failed",
+                        "url": url,
+                        "error": failure.message,
+                        "error_code": failure.kind,
+                        "retryable": failure.kind.retryable(),
+                        "exit_code": code,
+                        "attempts": attempts,
+                    })
+                ),
+                false => eprintln!("❌ {}", failure.message),
+            }
+            code
+        }
+    }
+}
+
+// Validate the arguments the same way the server validates its forms
+fn parse_options(
+    format: Option<String>,
+    subtitles: Option<String>,
+    proxy: Option<String>,
+    url: &str,
+) -> Result<ConvertOptions, String> {
+    validate_youtube_url(url)?;
+    let format = format
+        .map(|format| format.trim().to_string())
+        .filter(|format| !format.is_empty());
+    Ok(ConvertOptions {
+        subtitles: subtitles::parse_languages(subtitles)?,
+        proxy: credentials::parse_proxy(proxy)?,
+        format,
+        ..ConvertOptions::default()
+    })
+}
+
+// Retry transient failures like the server does, returning the number of runs
+async fn convert_with_retries(
+    url: &str,
+    options: &ConvertOptions,
+    config: &Config,
+    staging: &Path,
+    abort: &CancellationToken,
+) -> (Result<Conversion, Failure>, u32) {
+    let max_attempts = config.retry_max_attempts;
+    let mut attempt = 1;
+    loop {
+        let result =
+            attempt_conversion(url, options, config, None, staging, abort, &render_progress).await;
+        let failure = match result {
+            Err(failure) if failure.kind.retryable() && attempt < max_attempts => failure,
+            result => return (result, attempt),
+        };
+        let delay = failures::backoff(
+            config.retry_base_delay_secs,
+            config.retry_max_delay_secs,
+            attempt,
+        );
+        render_progress(Progress::Note(format!(
+            "{} Retrying in {:.1}s (attempt {} of {})",
+            failure.message,
+            delay.as_secs_f64(),
+            attempt + 1,
+            max_attempts
+        )));
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = abort.cancelled() => return (Err(Failure::new(FailureKind::Cancelled)), attempt),
+        }
+        attempt += 1;
+    }
+}
+
+// Exit code of a failed conversion, one per class of failure
+fn exit_code(kind: FailureKind) -> i32 {
+    match kind {
+        FailureKind::VideoUnavailable
+        | FailureKind::PrivateVideo
+        | FailureKind::AgeRestricted
+        | FailureKind::GeoBlocked
+        | FailureKind::LiveNotStarted
+        | FailureKind::UnsupportedUrl => 3,
+        FailureKind::DurationLimit
+        | FailureKind::SizeLimit
+        | FailureKind::LiveStream
+        | FailureKind::Timeout => 4,
+        FailureKind::NetworkError => 5,
+        FailureKind::YtDlpMissing | FailureKind::FfmpegMissing => 6,
+        FailureKind::DiskFull => 7,
+        // As shells report a process stopped by Ctrl-C
+        FailureKind::Cancelled => 130,
+        FailureKind::Internal => 1,
+    }
+}
+
+// Files of a conversion after moving them to the output directory
+struct Outputs {
+    audio: PathBuf,
+    thumbnail: Option<PathBuf>,
+    artifacts: Vec<(usize, PathBuf)>,
+}
+
+// Move the files out of the staging directory; the waveforms only serve the
+// web player and stay behind
+fn move_outputs(conversion: &Conversion, staging: &Path, out: &Path) -> Result<Outputs, String> {
+    fs::create_dir_all(out).map_err(|e| format!("Cannot create {}: {}", out.display(), e))?;
+    let mut outputs = Outputs {
+        audio: move_file(staging, &conversion.audio, out)?,
+        thumbnail: conversion
+            .thumbnail
+            .as_deref()
+            .map(|thumbnail| move_file(staging, thumbnail, out))
+            .transpose()?,
+        artifacts: Vec::new(),
+    };
+    for (index, artifact) in conversion.artifacts.iter().enumerate() {
+        if artifact.filename == conversion.audio {
+            // The zip of a chapter split is also the main file
+            outputs.artifacts.push((index, outputs.audio.clone()));
+        } else if artifact.kind != ArtifactKind::Waveform {
+            outputs
+                .artifacts
+                .push((index, move_file(staging, &artifact.filename, out)?));
+        }
+    }
+    Ok(outputs)
+}
+
+fn move_file(staging: &Path, filename: &str, out: &Path) -> Result<PathBuf, String> {
+    let source = staging.join(filename);
+    let target = out.join(filename);
+    // Renaming fails across file systems, copying does not
+    if fs::rename(&source, &target).is_err() {
+        fs::copy(&source, &target)
+            .and_then(|_| fs::remove_file(&source))
+            .map_err(|e| format!("Cannot move {} to {}: {}", filename, out.display(), e))?;
+    }
+    Ok(target)
+}
+
+fn result_json(
+    url: &str,
+    conversion: &Conversion,
+    outputs: &Outputs,
+    attempts: u32,
+    out: &Path,
+) -> Value {
+    let artifacts: Vec<Value> = outputs
+        .artifacts
+        .iter()
+        .map(|(index, path)| {
+            let mut artifact = json!(conversion.artifacts[*index]);
+            artifact["path"] = json!(path);
+            artifact
+        })
+        .collect();
+    let mut result = json!({
+        "status": "completed",
+        "url": url,
+        "out_dir": out,
+        "file": outputs.audio,
+        "thumbnail": outputs.thumbnail,
+        "duration": conversion.duration,
+        "artifacts": artifacts,
+        "attempts": attempts,
+    });
+    if let Some(loudness) = &conversion.loudness {
+        result["loudness"] = json!(loudness);
+    }
+    result
+}
+
+// Show progress on stderr: one updating line on a terminal, nothing but the
+// notes otherwise, so redirected logs stay readable
+fn render_progress(progress: Progress) {
+    let terminal = std::io::stderr().is_terminal();
+    let mut stderr = std::io::stderr().lock();
+    let _ = match progress {
+        Progress::Note(note) => {
+            clear_line();
+            writeln!(stderr, "ℹ️  {}", note)
+        }
+        _ if !terminal => Ok(()),
+        Progress::Step(step) => write!(stderr, "\r\x1b[2K🔄 {}…", step),
+        Progress::Downloaded(percent) => {
+            let filled = (percent.clamp(0.0, 100.0) / 100.0 * BAR_WIDTH as f64) as usize;
+            write!(
+                stderr,
+                "\r\x1b[2K⬇️  Downloading [{}{}] {:5.1}%",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                percent
+            )
+        }
+    };
+    let _ = stderr.flush();
+}
+
+// Remove the progress line before other output
+fn clear_line() {
+    if std::io::stderr().is_terminal() {
+        eprint!("\r\x1b[2K");
+    }
+}
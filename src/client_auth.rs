@@ -0,0 +1,136 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::request::Parts,
+    middleware::AddExtension,
+    Extension,
+};
+use axum_server::{accept::Accept, tls_rustls::RustlsAcceptor};
+use rustls::{
+    server::{
+        AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientCertVerifier,
+        NoClientAuth,
+    },
+    RootCertStore,
+};
+use std::{convert::Infallible, future::Future, io, pin::Pin, sync::Arc};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+use yt_mp3_service::certs;
+
+use crate::{
+    config::{ClientAuth, Config, IdentityField},
+    tls,
+};
+
+// User named by the client certificate of the connection, None when the
+// client presented no certificate or the plain HTTP listener was used
+#[derive(Debug, Clone, Default)]
+pub struct ClientIdentity(pub Option<String>);
+
+impl ClientIdentity {
+    // Tasks without an owner are visible to everyone
+    pub fn may_access(&self, owner: Option<&str>) -> bool {
+        match owner {
+            Some(owner) => self.0.as_deref() == Some(owner),
+            None => true,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIdentity {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<ClientIdentity>().cloned().unwrap_or_default())
+    }
+}
+
+// Client certificate policy for the HTTPS listener
+pub fn verifier(config: &Config) -> Result<Arc<dyn ClientCertVerifier>, String> {
+    if config.client_auth == ClientAuth::Off {
+        return Ok(NoClientAuth::boxed());
+    }
+
+    let ca_path = config
+        .client_ca_path
+        .clone()
+        .unwrap_or_else(|| tls::ca_dir(config).join(certs::CA_CERT_FILE));
+    let mut roots = RootCertStore::empty();
+    for ca in tls::read_cert_chain(&ca_path)? {
+        roots
+            .add(&ca)
+            .map_err(|e| format!("Invalid client CA in {}: {}", ca_path.display(), e))?;
+    }
+    if roots.is_empty() {
+        return Err(format!("No client CA certificate found in {}", ca_path.display()));
+    }
+
+    Ok(match config.client_auth {
+        ClientAuth::Required => AllowAnyAuthenticatedClient::new(roots).boxed(),
+        _ => AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed(),
+    })
+}
+
+// TLS acceptor that attaches the client certificate identity to every
+// request of the connection
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+    field: IdentityField,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(inner: RustlsAcceptor, field: IdentityField) -> Self {
+        ClientCertAcceptor { inner, field }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, ClientIdentity>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let inner = self.inner.clone();
+        let field = self.field;
+        Box::pin(async move {
+            let (stream, service) = inner.accept(stream, service).await?;
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|leaf| identity_of(&leaf.0, field));
+            Ok((stream, Extension(ClientIdentity(identity)).layer(service)))
+        })
+    }
+}
+
+// Map a verified client certificate to a user name
+fn identity_of(cert_der: &[u8], field: IdentityField) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert_der).ok()?;
+    match field {
+        IdentityField::CommonName => cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string),
+        IdentityField::Email => {
+            let extension = cert.subject_alternative_name().ok()??;
+            extension.value.general_names.iter().find_map(|name| match name {
+                GeneralName::RFC822Name(email) => Some(email.to_ascii_lowercase()),
+                _ => None,
+            })
+        }
+        IdentityField::Subject => Some(cert.subject().to_string()),
+    }
+}
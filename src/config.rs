@@ -0,0 +1,377 @@
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::tools::{self, Tool};
+
+// Directory where converted audio and thumbnails are stored
+pub const DOWNLOAD_DIR: &str = "downloads";
+// Server state, kept apart from the downloads since it holds owners, callback
+// URLs and proxy passwords
+pub const STATE_DIR: &str = "state";
+// State files earlier versions kept in the downloads directory
+const LEGACY_STATE_FILES: &[&str] = &["tasks.json", "batches.json", "feed-secret"];
+// Upper bound of YT_MP3_WEBHOOK_MAX_ATTEMPTS; the last retry already waits weeks
+const MAX_WEBHOOK_ATTEMPTS: u32 = 20;
+
+// Which listeners run and what the plain HTTP listener serves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerMode {
+    // Full app on both HTTP and HTTPS
+    Both,
+    // Full app on HTTP only, e.g. behind a TLS-terminating proxy
+    HttpOnly,
+    // Full app on HTTPS only
+    HttpsOnly,
+    // Full app on HTTPS, HTTP answers with redirects to HTTPS
+    Redirect,
+}
+
+impl ListenerMode {
+    pub fn serves_http(self) -> bool {
+        self != ListenerMode::HttpsOnly
+    }
+
+    pub fn serves_https(self) -> bool {
+        self != ListenerMode::HttpOnly
+    }
+}
+
+impl FromStr for ListenerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "both" => Ok(ListenerMode::Both),
+            "http" | "http-only" => Ok(ListenerMode::HttpOnly),
+            "https" | "https-only" => Ok(ListenerMode::HttpsOnly),
+            "redirect" => Ok(ListenerMode::Redirect),
+            other => Err(format!("unknown listener mode: {}", other)),
+        }
+    }
+}
+
+// How the ACME server validates control of the certificate's names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcmeChallenge {
+    // Token served at `/.well-known/acme-challenge/` on the HTTP listener
+    Http01,
+    // Special certificate presented on the HTTPS listener for `acme-tls/1`
+    TlsAlpn01,
+}
+
+impl FromStr for AcmeChallenge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "http-01" | "http" => Ok(AcmeChallenge::Http01),
+            "tls-alpn-01" | "tls-alpn" => Ok(AcmeChallenge::TlsAlpn01),
+            other => Err(format!("unknown ACME challenge type: {}", other)),
+        }
+    }
+}
+
+// Whether HTTPS clients must present a certificate from the client CA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    Off,
+    // Certificates are verified when presented, anonymous clients are allowed
+    Optional,
+    Required,
+}
+
+impl FromStr for ClientAuth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(ClientAuth::Off),
+            "optional" => Ok(ClientAuth::Optional),
+            "required" | "require" => Ok(ClientAuth::Required),
+            other => Err(format!("unknown client auth mode: {}", other)),
+        }
+    }
+}
+
+// Which part of a client certificate names the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityField {
+    CommonName,
+    // First email address among the subject alternative names
+    Email,
+    // Full distinguished name
+    Subject,
+}
+
+impl FromStr for IdentityField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cn" | "common-name" => Ok(IdentityField::CommonName),
+            "email" => Ok(IdentityField::Email),
+            "subject" | "dn" => Ok(IdentityField::Subject),
+            other => Err(format!("unknown client identity field: {}", other)),
+        }
+    }
+}
+
+// Image format thumbnails are converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    Jpeg,
+    Png,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Png => "png",
+        }
+    }
+}
+
+impl FromStr for ThumbnailFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(ThumbnailFormat::Jpeg),
+            "png" => Ok(ThumbnailFormat::Png),
+            other => Err(format!("unknown thumbnail format: {}", other)),
+        }
+    }
+}
+
+// Runtime configuration, read from `YT_MP3_*` environment variables
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Resolved at startup from the variable, `bin/`, then `PATH`
+    pub yt_dlp_path: PathBuf,
+    pub ffmpeg_path: PathBuf,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    // Reload the certificate when its files change
+    pub tls_watch: bool,
+    // Issue certificates from the local CA when missing or close to expiry
+    pub cert_auto: bool,
+    pub cert_renew_days: i64,
+    // Include the hostname and LAN addresses in issued certificates
+    pub cert_lan: bool,
+    pub cert_interfaces: Vec<String>,
+    pub cert_exclude_interfaces: Vec<String>,
+    pub min_free_space_mb: u64,
+    pub cert_expiry_warn_days: i64,
+    pub max_concurrent_conversions: usize,
+    pub max_queued_conversions: usize,
+    // URLs accepted in one batch submission
+    pub max_batch_size: usize,
+    // Normalize every conversion, not only those that ask for it
+    pub normalize: bool,
+    // Integrated loudness target in LUFS and true-peak ceiling in dBTP
+    pub loudnorm_target: f64,
+    pub loudnorm_true_peak: f64,
+    pub thumbnail_format: ThumbnailFormat,
+    // Crop every thumbnail to a centred square, as album art
+    pub thumbnail_square: bool,
+    // Longest edges of the variants served with `?size=`, ascending
+    pub thumbnail_sizes: Vec<u32>,
+    pub shutdown_timeout_secs: u64,
+    pub listener_mode: ListenerMode,
+    pub http_addr: SocketAddr,
+    pub https_addr: SocketAddr,
+    // Port clients use to reach HTTPS, for redirects behind port forwarding
+    pub public_https_port: u16,
+    // `Strict-Transport-Security` max-age in seconds, 0 disables the header
+    pub hsts_max_age: u64,
+    pub hsts_include_subdomains: bool,
+    // ACME directory URL; certificates come from the ACME server when set
+    pub acme_directory: Option<String>,
+    pub acme_domains: Vec<String>,
+    // Contact URIs for the ACME account, e.g. `mailto:admin@example.com`
+    pub acme_contact: Vec<String>,
+    pub acme_challenge: AcmeChallenge,
+    // Extra root certificate trusted for the ACME server, e.g. Pebble's
+    pub acme_ca_root: Option<PathBuf>,
+    pub acme_account_path: PathBuf,
+    pub client_auth: ClientAuth,
+    // PEM bundle of CAs trusted for client certificates, the local CA when unset
+    pub client_ca_path: Option<PathBuf>,
+    pub client_identity: IdentityField,
+    // Endpoints notified about every finished task, besides per-task callbacks
+    pub webhook_urls: Vec<String>,
+    // HMAC-SHA256 key for the `X-YT-MP3-Signature` header, unsigned when unset
+    pub webhook_secret: Option<String>,
+    // Between 1 and MAX_WEBHOOK_ATTEMPTS; the retry delay doubles with each one
+    pub webhook_max_attempts: u32,
+    pub webhook_timeout_secs: u64,
+    // Callback hosts allowed to resolve to loopback, private or link-local addresses
+    pub webhook_allowed_hosts: Vec<String>,
+    // Wall-clock limit of one conversion run, 0 (the default) for none
+    pub task_timeout_secs: u64,
+    // Longest video accepted and largest download or MP3, 0 (the default) for
+    // no limit; opt-in, so upgraded deployments keep converting what they did
+    pub max_duration_secs: u64,
+    pub max_filesize_mb: u64,
+    // Netscape cookies.txt used for every download without an uploaded jar
+    pub cookies_path: Option<PathBuf>,
+    // Where uploaded per-user cookie jars are kept, outside the downloads
+    pub cookies_dir: PathBuf,
+    // Proxy URL for yt-dlp, overridden per request
+    pub proxy: Option<String>,
+    // Runs of a conversion failing with a retryable error, the first included
+    pub retry_max_attempts: u32,
+    // Backoff before the second run, doubled for each further one up to the maximum
+    pub retry_base_delay_secs: u64,
+    pub retry_max_delay_secs: u64,
+    // Scheme and host of podcast feed links and webhook download URLs, taken
+    // from the request when unset
+    pub public_url: Option<String>,
+    // Key deriving the feed IDs, generated and saved in the state directory when unset
+    pub feed_secret: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let https_addr = env_or("YT_MP3_HTTPS_ADDR", SocketAddr::from(([127, 0, 0, 1], 3443)));
+        Config {
+            yt_dlp_path: tools::locate(Tool::YtDlp, env_opt("YT_MP3_YT_DLP").map(PathBuf::from)),
+            ffmpeg_path: tools::locate(Tool::Ffmpeg, env_opt("YT_MP3_FFMPEG").map(PathBuf::from)),
+            cert_path: env_or("YT_MP3_TLS_CERT", PathBuf::from("certs/cert.pem")),
+            key_path: env_or("YT_MP3_TLS_KEY", PathBuf::from("certs/key.pem")),
+            tls_watch: env_or("YT_MP3_TLS_WATCH", true),
+            cert_auto: env_or("YT_MP3_CERT_AUTO", true),
+            cert_renew_days: env_or("YT_MP3_CERT_RENEW_DAYS", 30),
+            cert_lan: env_or("YT_MP3_CERT_LAN", true),
+            cert_interfaces: env_list("YT_MP3_CERT_INTERFACES"),
+            cert_exclude_interfaces: env_list("YT_MP3_CERT_EXCLUDE_INTERFACES"),
+            min_free_space_mb: env_or("YT_MP3_MIN_FREE_SPACE_MB", 500),
+            cert_expiry_warn_days: env_or("YT_MP3_CERT_EXPIRY_WARN_DAYS", 14),
+            max_concurrent_conversions: env_or("YT_MP3_MAX_CONCURRENT", 4).max(1),
+            max_queued_conversions: env_or("YT_MP3_MAX_QUEUED", 32),
+            max_batch_size: env_or("YT_MP3_MAX_BATCH_SIZE", 50).max(1),
+            normalize: env_or("YT_MP3_NORMALIZE", false),
+            // Limits of ffmpeg's loudnorm filter
+            loudnorm_target: env_or("YT_MP3_LOUDNORM_TARGET", -16.0_f64).clamp(-70.0, -5.0),
+            loudnorm_true_peak: env_or("YT_MP3_LOUDNORM_TRUE_PEAK", -1.5_f64).clamp(-9.0, 0.0),
+            thumbnail_format: env_or("YT_MP3_THUMBNAIL_FORMAT", ThumbnailFormat::Jpeg),
+            thumbnail_square: env_or("YT_MP3_THUMBNAIL_SQUARE", false),
+            thumbnail_sizes: thumbnail_sizes(),
+            shutdown_timeout_secs: env_or("YT_MP3_SHUTDOWN_TIMEOUT_SECS", 30),
+            listener_mode: env_or("YT_MP3_LISTENER_MODE", ListenerMode::Both),
+            http_addr: env_or("YT_MP3_HTTP_ADDR", SocketAddr::from(([127, 0, 0, 1], 3000))),
+            https_addr,
+            public_https_port: env_or("YT_MP3_PUBLIC_HTTPS_PORT", https_addr.port()),
+            hsts_max_age: env_or("YT_MP3_HSTS_MAX_AGE", 0),
+            hsts_include_subdomains: env_or("YT_MP3_HSTS_INCLUDE_SUBDOMAINS", false),
+            acme_directory: env_opt("YT_MP3_ACME_DIRECTORY"),
+            acme_domains: env_list("YT_MP3_ACME_DOMAINS"),
+            acme_contact: env_list("YT_MP3_ACME_CONTACT"),
+            acme_challenge: env_or("YT_MP3_ACME_CHALLENGE", AcmeChallenge::Http01),
+            acme_ca_root: env_opt("YT_MP3_ACME_CA_ROOT").map(PathBuf::from),
+            acme_account_path: env_or(
+                "YT_MP3_ACME_ACCOUNT",
+                PathBuf::from("certs/acme-account.json"),
+            ),
+            client_auth: env_or("YT_MP3_CLIENT_AUTH", ClientAuth::Off),
+            client_ca_path: env_opt("YT_MP3_CLIENT_CA").map(PathBuf::from),
+            client_identity: env_or("YT_MP3_CLIENT_IDENTITY", IdentityField::CommonName),
+            webhook_urls: env_list("YT_MP3_WEBHOOK_URLS"),
+            webhook_secret: env_opt("YT_MP3_WEBHOOK_SECRET"),
+            webhook_max_attempts: env_or("YT_MP3_WEBHOOK_MAX_ATTEMPTS", 5).clamp(1, MAX_WEBHOOK_ATTEMPTS),
+            webhook_timeout_secs: env_or("YT_MP3_WEBHOOK_TIMEOUT_SECS", 10),
+            webhook_allowed_hosts: env_list("YT_MP3_WEBHOOK_ALLOWED_HOSTS"),
+            task_timeout_secs: env_or("YT_MP3_TASK_TIMEOUT_SECS", 0),
+            max_duration_secs: env_or("YT_MP3_MAX_DURATION_SECS", 0),
+            max_filesize_mb: env_or("YT_MP3_MAX_FILESIZE_MB", 0),
+            cookies_path: env_opt("YT_MP3_COOKIES").map(PathBuf::from),
+            cookies_dir: env_or("YT_MP3_COOKIES_DIR", PathBuf::from("cookies")),
+            proxy: env_opt("YT_MP3_PROXY"),
+            retry_max_attempts: env_or("YT_MP3_RETRY_MAX_ATTEMPTS", 3).max(1),
+            retry_base_delay_secs: env_or("YT_MP3_RETRY_BASE_DELAY_SECS", 5),
+            retry_max_delay_secs: env_or("YT_MP3_RETRY_MAX_DELAY_SECS", 300),
+            public_url: env_opt("YT_MP3_PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string()),
+            feed_secret: env_opt("YT_MP3_FEED_SECRET"),
+        }
+    }
+
+    // Certificates are ordered from the ACME server for the configured domains
+    pub fn acme_enabled(&self) -> bool {
+        self.acme_directory.is_some() && !self.acme_domains.is_empty()
+    }
+}
+
+// Parse an environment variable, falling back to the default when unset or invalid
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => match value.trim().parse() {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                eprintln!("⚠️  Ignoring invalid value for {}: {:?}", name, value);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// Move state files left in the downloads directory by earlier versions
+pub fn move_legacy_state() {
+    for name in LEGACY_STATE_FILES {
+        let legacy = Path::new(DOWNLOAD_DIR).join(name);
+        let path = Path::new(STATE_DIR).join(name);
+        if !legacy.is_file() || path.exists() {
+            continue;
+        }
+        match fs::rename(&legacy, &path) {
+            Ok(()) => println!("ℹ️  Moved {} to {}", legacy.display(), path.display()),
+            Err(e) => eprintln!(
+                "⚠️  Cannot move {} to {}: {}",
+                legacy.display(),
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+// Non-empty environment variable, None when unset or blank
+fn env_opt(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// `YT_MP3_THUMBNAIL_SIZES`, falling back to the defaults when unset or invalid
+fn thumbnail_sizes() -> Vec<u32> {
+    let mut sizes = Vec::new();
+    for item in env_list("YT_MP3_THUMBNAIL_SIZES") {
+        match item.parse::<u32>() {
+            Ok(size) if (16..=4096).contains(&size) => sizes.push(size),
+            _ => eprintln!("⚠️  Ignoring invalid thumbnail size: {:?}", item),
+        }
+    }
+    if sizes.is_empty() {
+        sizes = vec![64, 128, 256, 512];
+    }
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
+
+// Comma-separated list from an environment variable, empty when unset
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
@@ -0,0 +1,326 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Why a conversion failed, reported to clients as a stable code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    VideoUnavailable,
+    PrivateVideo,
+    AgeRestricted,
+    GeoBlocked,
+    LiveNotStarted,
+    NetworkError,
+    FfmpegMissing,
+    YtDlpMissing,
+    UnsupportedUrl,
+    DiskFull,
+    // Over the configured limits, or running longer than allowed
+    DurationLimit,
+    SizeLimit,
+    LiveStream,
+    Timeout,
+    // Stopped by a shutdown or restart of the server
+    Cancelled,
+    // Anything not recognised; the details are only logged
+    Internal,
+}
+
+impl FailureKind {
+    // Message shown to users in place of the tool output
+    pub fn message(self) -> &'static str {
+        match self {
+            FailureKind::VideoUnavailable => {
+                "This video is unavailable. It may have been removed or the link may be wrong."
+            }
+            FailureKind::PrivateVideo => "This video is private.",
+            FailureKind::AgeRestricted => {
+                "This video is age-restricted and cannot be downloaded without signing in."
+            }
+            FailureKind::GeoBlocked => "This video is not available in the server's country.",
+            FailureKind::LiveNotStarted => "This live stream or premiere has not started yet.",
+            FailureKind::NetworkError => "YouTube could not be reached. Please try again later.",
+            FailureKind::FfmpegMissing => "FFmpeg is not installed on the server.",
+            FailureKind::YtDlpMissing => "yt-dlp is not installed on the server.",
+            FailureKind::UnsupportedUrl => "This URL is not supported.",
+            FailureKind::DiskFull => "The server has run out of disk space.",
+            FailureKind::DurationLimit => "This video is longer than the server allows.",
+            FailureKind::SizeLimit => "This video is larger than the server allows.",
+            FailureKind::LiveStream => "Live streams cannot be converted until they have ended.",
+            FailureKind::Timeout => "The conversion took longer than the server allows.",
+            FailureKind::Cancelled => "Cancelled: server is shutting down",
+            FailureKind::Internal => "Conversion failed.",
+        }
+    }
+
+    // Whether the same request may succeed when tried again shortly
+    pub fn retryable(self) -> bool {
+        matches!(self, FailureKind::NetworkError)
+    }
+}
+
+// A failed conversion: what kind of failure, and what to tell the user
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub message: String,
+    // Tool output or error behind the failure, only logged with the task
+    pub detail: Option<String>,
+}
+
+impl Failure {
+    pub fn new(kind: FailureKind) -> Self {
+        Failure {
+            kind,
+            message: kind.message().to_string(),
+            detail: None,
+        }
+    }
+
+    // Classify the error output of a failed yt-dlp run
+    //
+    // The output itself is never shown to users; unrecognised errors get a
+    // generic message.
+    pub fn from_yt_dlp(stderr: &str) -> Self {
+        let kind = classify(stderr).unwrap_or(FailureKind::Internal);
+        let mut failure = Failure::new(kind);
+        if kind == FailureKind::Internal {
+            failure.message = "yt-dlp could not download this video.".to_string();
+        }
+        failure.detail = Some(stderr.trim().to_string());
+        failure
+    }
+}
+
+// Errors of the later conversion steps may quote tool output and paths, so
+// they are kept as the detail; users get the message of the known cause, such
+// as a full disk, or the generic one.
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        let kind = classify(&message).unwrap_or(FailureKind::Internal);
+        Failure {
+            detail: Some(message),
+            ..Failure::new(kind)
+        }
+    }
+}
+
+// Wait before run `attempt + 1` of a conversion
+//
+// The delay doubles from `base_secs` with each attempt up to `max_secs`, and a
+// random half of it is dropped so tasks that failed together spread out.
+pub fn backoff(base_secs: u64, max_secs: u64, attempt: u32) -> Duration {
+    let delay = base_secs
+        .saturating_mul(1 << attempt.saturating_sub(1).min(20))
+        .min(max_secs);
+    let delay = Duration::from_secs(delay);
+    delay / 2 + delay.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+}
+
+// Known failure behind an error output, from the most specific pattern to
+// the most general one: a private video is also reported as unavailable.
+fn classify(output: &str) -> Option<FailureKind> {
+    const PATTERNS: &[(FailureKind, &[&str])] = &[
+        (FailureKind::YtDlpMissing, &["cannot execute yt-dlp"]),
+        (
+            FailureKind::FfmpegMissing,
+            &[
+                "ffmpeg not found",
+                "ffprobe and ffmpeg not found",
+                "ffmpeg is not installed",
+                "cannot execute ffmpeg",
+            ],
+        ),
+        (
+            FailureKind::DiskFull,
+            &[
+                "no space left on device",
+                "errno 28",
+                "os error 28",
+                "not enough space on the disk",
+                "os error 112",
+            ],
+        ),
+        (
+            FailureKind::PrivateVideo,
+            &["private video", "this video is private"],
+        ),
+        (
+            FailureKind::AgeRestricted,
+            &[
+                "sign in to confirm your age",
+                "age-restricted",
+                "age restricted",
+                "inappropriate for some users",
+            ],
+        ),
+        (
+            FailureKind::GeoBlocked,
+            &[
+                "not available in your country",
+                "not made this video available in your country",
+                "blocked it in your country",
+                "geo restriction",
+                "geo-restriction",
+                "geo-restricted",
+            ],
+        ),
+        (
+            FailureKind::LiveNotStarted,
+            &[
+                "this live event will begin",
+                "live event will begin in",
+                "premieres in",
+                "premiere will begin",
+                "waiting for scheduled stream",
+            ],
+        ),
+        (
+            FailureKind::UnsupportedUrl,
+            &[
+                "unsupported url",
+                "is not a valid url",
+                "no suitable extractor",
+            ],
+        ),
+        (
+            FailureKind::VideoUnavailable,
+            &[
+                "video unavailable",
+                "this video is unavailable",
+                "this video has been removed",
+                "this video does not exist",
+                "account associated with this video has been terminated",
+                "http error 404",
+            ],
+        ),
+        (
+            FailureKind::NetworkError,
+            &[
+                "unable to download webpage",
+                "unable to download api page",
+                "unable to connect",
+                "unable to connect to proxy",
+                "tunnel connection failed",
+                "connection reset",
+                "connection refused",
+                "connection aborted",
+                "timed out",
+                "temporary failure in name resolution",
+                "name or service not known",
+                "nodename nor servname",
+                "getaddrinfo failed",
+                "network is unreachable",
+                "remote end closed connection",
+                "incompleteread",
+                "http error 429",
+                "http error 500",
+                "http error 502",
+                "http error 503",
+                "http error 504",
+            ],
+        ),
+    ];
+
+    let output = output.to_lowercase();
+    PATTERNS
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|pattern| output.contains(pattern)))
+        .map(|&(kind, _)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_prefers_the_specific_cause() {
+        // Private videos are reported as unavailable too
+        assert_eq!(
+            classify("ERROR: [youtube] abc: Private video. Sign in if you've been granted access"),
+            Some(FailureKind::PrivateVideo)
+        );
+        assert_eq!(
+            classify("ERROR: [youtube] abc: Video unavailable"),
+            Some(FailureKind::VideoUnavailable)
+        );
+        assert_eq!(
+            classify("ERROR: Postprocessing: ffprobe and ffmpeg not found"),
+            Some(FailureKind::FfmpegMissing)
+        );
+        assert_eq!(
+            classify("OSError: [Errno 28] No space left on device"),
+            Some(FailureKind::DiskFull)
+        );
+    }
+
+    #[test]
+    fn classify_ignores_case() {
+        assert_eq!(
+            classify("ERROR: Unable to download webpage: HTTP Error 503"),
+            Some(FailureKind::NetworkError)
+        );
+        assert_eq!(
+            classify("SIGN IN TO CONFIRM YOUR AGE"),
+            Some(FailureKind::AgeRestricted)
+        );
+        assert_eq!(
+            classify("The uploader has not made this video available in your country"),
+            Some(FailureKind::GeoBlocked)
+        );
+    }
+
+    #[test]
+    fn classify_leaves_unknown_output() {
+        assert_eq!(classify(""), None);
+        assert_eq!(classify("ERROR: something new went wrong"), None);
+        // Cancellation is reported by the steps themselves, never parsed
+        assert_eq!(classify("server is shutting down"), None);
+    }
+
+    #[test]
+    fn failures_never_carry_tool_output() {
+        let failure = Failure::from_yt_dlp("ERROR: /home/user/secret: weird\n");
+        assert_eq!(failure.kind, FailureKind::Internal);
+        assert_eq!(failure.message, "yt-dlp could not download this video.");
+        assert_eq!(
+            failure.detail.as_deref(),
+            Some("ERROR: /home/user/secret: weird")
+        );
+
+        let failure = Failure::from("Loudness normalization failed: /tmp/x.mp3: bad".to_string());
+        assert_eq!(failure.kind, FailureKind::Internal);
+        assert_eq!(failure.message, FailureKind::Internal.message());
+        assert_eq!(
+            failure.detail.as_deref(),
+            Some("Loudness normalization failed: /tmp/x.mp3: bad")
+        );
+
+        let failure = Failure::from("Cannot write x.zip: No space left on device".to_string());
+        assert_eq!(failure.kind, FailureKind::DiskFull);
+        assert_eq!(failure.message, FailureKind::DiskFull.message());
+    }
+
+    #[test]
+    fn only_network_errors_are_retryable() {
+        assert!(FailureKind::NetworkError.retryable());
+        assert!(!FailureKind::Timeout.retryable());
+        assert!(!FailureKind::Cancelled.retryable());
+        assert!(!FailureKind::Internal.retryable());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        for (attempt, full) in [(1, 4), (2, 8), (3, 16), (4, 20), (40, 20)] {
+            let delay = backoff(4, 20, attempt);
+            let full = Duration::from_secs(full);
+            assert!(
+                delay >= full / 2 && delay <= full,
+                "{:?} for {}",
+                delay,
+                attempt
+            );
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Request, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path as FsPath, PathBuf},
+};
+use time::OffsetDateTime;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+use yt_mp3_service::certs;
+
+use crate::{
+    client_auth::ClientIdentity,
+    config::{Config, DOWNLOAD_DIR, STATE_DIR},
+    history::{display_title, escape, url_path_segment},
+    tasks::{ArtifactKind, TaskRecord, TaskStatus},
+    AppState,
+};
+
+// Bitrate yt-dlp is asked for, used to estimate durations that were not recorded
+const ESTIMATE_BITRATE: f64 = 192_000.0;
+
+// Key deriving the feed IDs
+//
+// Feed URLs are meant for podcast apps, which cannot present client
+// certificates, so the unguessable ID is what grants access to a feed.
+pub struct Feeds {
+    secret: Vec<u8>,
+}
+
+impl Feeds {
+    // Use the configured secret, or one generated on first start and kept
+    // in the state directory so feed URLs survive restarts
+    pub fn load(config: &Config) -> Self {
+        if let Some(secret) = &config.feed_secret {
+            return Feeds {
+                secret: secret.as_bytes().to_vec(),
+            };
+        }
+
+        let path = PathBuf::from(STATE_DIR).join("feed-secret");
+        if let Ok(secret) = fs::read_to_string(&path) {
+            let secret = secret.trim();
+            if !secret.is_empty() {
+                return Feeds {
+                    secret: secret.as_bytes().to_vec(),
+                };
+            }
+        }
+
+        let mut bytes = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        let secret = hex(&bytes);
+        if let Err(e) = certs::write_private(&path, &secret) {
+            eprintln!(
+                "⚠️  Failed to save feed secret to {}, feed URLs will change on restart: {}",
+                path.display(),
+                e
+            );
+        }
+        Feeds {
+            secret: secret.into_bytes(),
+        }
+    }
+
+    fn id(&self, scope: &Scope) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(scope.key().as_bytes());
+        let mut id = hex(&mac.finalize().into_bytes());
+        id.truncate(32);
+        id
+    }
+}
+
+// Tasks a feed lists: everything of one user, or one of their collections
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Scope {
+    User(Option<String>),
+    Collection(Option<String>, String),
+}
+
+impl Scope {
+    fn key(&self) -> String {
+        match self {
+            Scope::User(owner) => format!("user\0{}", owner.as_deref().unwrap_or_default()),
+            Scope::Collection(owner, name) => format!(
+                "collection\0{}\0{}",
+                owner.as_deref().unwrap_or_default(),
+                name
+            ),
+        }
+    }
+
+    fn contains(&self, record: &TaskRecord) -> bool {
+        match self {
+            Scope::User(owner) => record.owner == *owner,
+            Scope::Collection(owner, name) => {
+                record.owner == *owner && record.collection.as_deref() == Some(name)
+            }
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Scope::User(Some(owner)) => format!("YouTube to MP3: {}", owner),
+            Scope::User(None) => "YouTube to MP3".to_string(),
+            Scope::Collection(_, name) => name.clone(),
+        }
+    }
+
+    // Every feed a task appears in
+    fn of(record: &TaskRecord) -> Vec<Scope> {
+        let mut scopes = vec![Scope::User(record.owner.clone())];
+        if let Some(name) = &record.collection {
+            scopes.push(Scope::Collection(record.owner.clone(), name.clone()));
+        }
+        scopes
+    }
+}
+
+// Scheme and host feed and webhook links are built from: `YT_MP3_PUBLIC_URL`,
+// or the Host header of the request
+pub struct BaseUrl(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for BaseUrl {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(url) = &state.config.public_url {
+            return Ok(BaseUrl(url.clone()));
+        }
+        let host = header_value(&parts.headers, header::HOST.as_str())
+            .ok_or((StatusCode::BAD_REQUEST, "Missing Host header"))?;
+        // The HTTPS listener attaches a client identity to every request
+        let scheme = if parts.extensions.get::<ClientIdentity>().is_some() {
+            "https"
+        } else {
+            header_value(&parts.headers, "x-forwarded-proto").unwrap_or("http")
+        };
+        Ok(BaseUrl(format!("{}://{}", scheme, host)))
+    }
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+// One episode: a converted MP3, or a track of a chapter split
+struct Item {
+    guid: String,
+    title: String,
+    link: Option<String>,
+    filename: String,
+    size: u64,
+    duration: f64,
+    episode: Option<u32>,
+    published: u64,
+    thumbnail: Option<String>,
+}
+
+// Episodes of the completed tasks in a feed, newest first
+fn items(tasks: &[(String, TaskRecord)], scope: &Scope) -> Vec<Item> {
+    let mut tasks: Vec<&(String, TaskRecord)> = tasks
+        .iter()
+        .filter(|(_, record)| scope.contains(record))
+        .collect();
+    tasks.sort_by_key(|(_, record)| std::cmp::Reverse(published(record)));
+
+    let mut items = Vec::new();
+    for (task_id, record) in tasks {
+        let TaskStatus::Completed(filename, thumbnail) = &record.status else {
+            continue;
+        };
+        let tracks: Vec<_> = record
+            .artifacts
+            .iter()
+            .filter(|artifact| artifact.kind == ArtifactKind::Track)
+            .collect();
+        let mut episodes = Vec::new();
+        if tracks.is_empty() {
+            episodes.push((
+                task_id.clone(),
+                display_title(record).to_string(),
+                filename.clone(),
+                record.duration,
+                None,
+            ));
+        }
+        for track in tracks {
+            episodes.push((
+                format!("{}#{}", task_id, track.track.unwrap_or_default()),
+                format!(
+                    "{}: {}",
+                    display_title(record),
+                    track.title.as_deref().unwrap_or(&track.filename)
+                ),
+                track.filename.clone(),
+                track.duration,
+                track.track,
+            ));
+        }
+
+        for (guid, title, filename, duration, episode) in episodes {
+            // Files removed by hand are left out rather than listed broken
+            let Ok(metadata) = fs::metadata(FsPath::new(DOWNLOAD_DIR).join(&filename)) else {
+                continue;
+            };
+            items.push(Item {
+                guid,
+                title,
+                link: record.url.clone(),
+                filename,
+                size: metadata.len(),
+                duration: duration.unwrap_or(metadata.len() as f64 * 8.0 / ESTIMATE_BITRATE),
+                episode,
+                published: published(record),
+                thumbnail: thumbnail.clone(),
+            });
+        }
+    }
+    items
+}
+
+fn published(record: &TaskRecord) -> u64 {
+    record.finished_at.unwrap_or(record.created_at)
+}
+
+// Find the feed an ID belongs to among the feeds that have tasks
+fn resolve(feeds: &Feeds, tasks: &[(String, TaskRecord)], feed_id: &str) -> Option<Scope> {
+    tasks
+        .iter()
+        .flat_map(|(_, record)| Scope::of(record))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .find(|scope| feeds.id(scope) == feed_id)
+}
+
+// GET /feeds/:feed_id.xml, an RSS 2.0 feed with iTunes podcast tags
+pub async fn feed(
+    State(state): State<AppState>,
+    BaseUrl(base): BaseUrl,
+    Path(feed_file): Path<String>,
+) -> Result<Response, StatusCode> {
+    let feed_id = feed_file
+        .strip_suffix(".xml")
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let tasks = state.tasks.list();
+    let scope = resolve(&state.feeds, &tasks, feed_id).ok_or(StatusCode::NOT_FOUND)?;
+    let items = items(&tasks, &scope);
+
+    let media = |filename: &str| {
+        escape(&format!(
+            "{}/feeds/{}/media/{}",
+            base,
+            feed_id,
+            url_path_segment(filename)
+        ))
+    };
+    let title = escape(&scope.title());
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+<channel>
+<title>{title}</title>
+<link>{link}</link>
+<description>Audio converted from YouTube videos: {title}</description>
+<language>en</language>
+<itunes:author>YouTube to MP3</itunes:author>
+<itunes:explicit>false</itunes:explicit>
+"#,
+        title = title,
+        link = escape(&format!("{}/history", base)),
+    );
+    if let Some(cover) = items.iter().find_map(|item| item.thumbnail.as_deref()) {
+        xml.push_str(&format!(
+            "<itunes:image href=\"{}\"/>\n<image><url>{}</url><title>{}</title><link>{}</link></image>\n",
+            media(cover),
+            media(cover),
+            title,
+            escape(&format!("{}/history", base)),
+        ));
+    }
+    for item in &items {
+        xml.push_str("<item>\n");
+        xml.push_str(&format!("<title>{}</title>\n", escape(&item.title)));
+        xml.push_str(&format!(
+            "<guid isPermaLink=\"false\">{}</guid>\n",
+            escape(&item.guid)
+        ));
+        if let Some(link) = &item.link {
+            xml.push_str(&format!("<link>{}</link>\n", escape(link)));
+        }
+        xml.push_str(&format!("<pubDate>{}</pubDate>\n", rfc2822(item.published)));
+        xml.push_str(&format!(
+            "<enclosure url=\"{}\" length=\"{}\" type=\"audio/mpeg\"/>\n",
+            media(&item.filename),
+            item.size
+        ));
+        xml.push_str(&format!(
+            "<itunes:duration>{}</itunes:duration>\n",
+            duration(item.duration)
+        ));
+        if let Some(episode) = item.episode {
+            xml.push_str(&format!("<itunes:episode>{}</itunes:episode>\n", episode));
+        }
+        if let Some(thumbnail) = &item.thumbnail {
+            xml.push_str(&format!("<itunes:image href=\"{}\"/>\n", media(thumbnail)));
+        }
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        xml,
+    )
+        .into_response())
+}
+
+// GET /feeds/:feed_id/media/:filename, with Range support for podcast apps
+// that seek or resume downloads
+pub async fn media(
+    State(state): State<AppState>,
+    Path((feed_id, filename)): Path<(String, String)>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    let tasks = state.tasks.list();
+    let scope = resolve(&state.feeds, &tasks, &feed_id).ok_or(StatusCode::NOT_FOUND)?;
+    // Only files of completed tasks in this feed are served
+    let listed = tasks.iter().any(|(_, record)| {
+        scope.contains(record)
+            && matches!(record.status, TaskStatus::Completed(..))
+            && record.files().contains(&filename.as_str())
+    });
+    if !listed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let response = ServeFile::new(FsPath::new(DOWNLOAD_DIR).join(&filename))
+        .oneshot(request)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(response.into_response())
+}
+
+// GET /api/v1/feeds, the feeds of the caller's tasks
+pub async fn list_feeds(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    BaseUrl(base): BaseUrl,
+) -> Json<Value> {
+    let tasks = state.tasks.list();
+    let scopes: BTreeSet<Scope> = tasks
+        .iter()
+        .filter(|(_, record)| record.owner == identity.0)
+        .flat_map(|(_, record)| Scope::of(record))
+        .collect();
+    let feeds: Vec<Value> = scopes
+        .iter()
+        .map(|scope| {
+            let feed_id = state.feeds.id(scope);
+            let collection = match scope {
+                Scope::Collection(_, name) => Some(name),
+                Scope::User(_) => None,
+            };
+            json!({
+                "feed_id": feed_id,
+                "title": scope.title(),
+                "collection": collection,
+                "url": format!("{}/feeds/{}.xml", base, feed_id),
+                "items": items(&tasks, scope).len(),
+            })
+        })
+        .collect();
+    Json(json!({ "feeds": feeds }))
+}
+
+// Date in RFC 2822 form, as RSS requires
+fn rfc2822(timestamp: u64) -> String {
+    let time =
+        OffsetDateTime::from_unix_timestamp(timestamp as i64).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        &time.weekday().to_string()[..3],
+        time.day(),
+        &time.month().to_string()[..3],
+        time.year(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+// `HH:MM:SS` as read by podcast apps
+pub fn duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,219 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    config::{Config, DOWNLOAD_DIR},
+    queue::ConversionQueue,
+    tools::{self, Tool},
+    AppState,
+};
+
+// How long the version probes of yt-dlp and FFmpeg are reused; orchestrators
+// check readiness every few seconds and each probe starts both programs
+const TOOL_CHECK_TTL: Duration = Duration::from_secs(60);
+
+// Outcome of a single readiness check
+#[derive(Clone)]
+struct Check {
+    ok: bool,
+    detail: Value,
+}
+
+impl Check {
+    fn pass(detail: Value) -> Self {
+        Check { ok: true, detail }
+    }
+
+    fn fail(detail: Value) -> Self {
+        Check { ok: false, detail }
+    }
+
+    fn into_json(self) -> Value {
+        let mut value = self.detail;
+        value["ok"] = Value::Bool(self.ok);
+        value
+    }
+}
+
+// Latest results of the tool checks, shared by all readiness requests
+#[derive(Default)]
+pub struct ToolChecks {
+    // When the tools were probed, and the yt-dlp and FFmpeg results
+    cached: Mutex<Option<(Instant, Check, Check)>>,
+}
+
+impl ToolChecks {
+    // Probe again once the results are older than the TTL; requests arriving
+    // meanwhile wait for that probe instead of starting their own
+    async fn get(&self, config: &Config) -> (Check, Check) {
+        let mut cached = self.cached.lock().await;
+        if let Some((checked_at, yt_dlp, ffmpeg)) = cached.as_ref() {
+            if checked_at.elapsed() < TOOL_CHECK_TTL {
+                return (yt_dlp.clone(), ffmpeg.clone());
+            }
+        }
+        let yt_dlp = check_tool(Tool::YtDlp, &config.yt_dlp_path).await;
+        let ffmpeg = check_tool(Tool::Ffmpeg, &config.ffmpeg_path).await;
+        *cached = Some((Instant::now(), yt_dlp.clone(), ffmpeg.clone()));
+        (yt_dlp, ffmpeg)
+    }
+}
+
+// Liveness: the process is up and the runtime is serving requests
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+// Readiness: every dependency needed to run a conversion is available
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let config = &state.config;
+    let (yt_dlp, ffmpeg) = state.tool_checks.get(config).await;
+    let checks = vec![
+        ("yt_dlp", yt_dlp),
+        ("ffmpeg", ffmpeg),
+        ("downloads", check_downloads(config.min_free_space_mb)),
+        ("certificate", check_certificate(&state)),
+        ("queue", check_queue(&state.queue)),
+    ];
+
+    let ready = checks.iter().all(|(_, check)| check.ok);
+    let mut results = serde_json::Map::new();
+    for (name, check) in checks {
+        results.insert(name.to_string(), check.into_json());
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": results,
+        })),
+    )
+}
+
+// Run the tool's version command, failing when it is older than supported
+async fn check_tool(tool: Tool, path: &Path) -> Check {
+    let mut detail = json!({
+        "path": path.display().to_string(),
+        "minimum_version": tool.minimum_version(),
+    });
+    match tools::probe(tool, path).await {
+        Ok(probe) => {
+            detail["version"] = Value::String(probe.banner);
+            if probe.supported == Some(false) {
+                detail["error"] = Value::String(format!(
+                    "version {} is older than {}",
+                    probe.version.unwrap_or_default(),
+                    tool.minimum_version()
+                ));
+                return Check::fail(detail);
+            }
+            Check::pass(detail)
+        }
+        Err(e) => {
+            detail["error"] = Value::String(e);
+            Check::fail(detail)
+        }
+    }
+}
+
+// The downloads directory accepts writes and has enough free space
+fn check_downloads(min_free_space_mb: u64) -> Check {
+    let dir = Path::new(DOWNLOAD_DIR);
+    let probe = dir.join(format!(".readyz-{}", Uuid::new_v4()));
+    let writable = match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    let free_mb = match fs2::available_space(dir) {
+        Ok(bytes) => bytes / (1024 * 1024),
+        Err(e) => {
+            return Check::fail(json!({
+                "path": DOWNLOAD_DIR,
+                "error": format!("cannot read free space: {}", e),
+            }))
+        }
+    };
+
+    let mut detail = json!({
+        "path": DOWNLOAD_DIR,
+        "writable": writable.is_ok(),
+        "free_mb": free_mb,
+        "min_free_mb": min_free_space_mb,
+    });
+    if let Err(e) = writable {
+        detail["error"] = Value::String(format!("not writable: {}", e));
+        return Check::fail(detail);
+    }
+    if free_mb < min_free_space_mb {
+        detail["error"] = Value::String("free space below threshold".to_string());
+        return Check::fail(detail);
+    }
+    Check::pass(detail)
+}
+
+// A certificate is loaded and is not about to expire
+fn check_certificate(state: &AppState) -> Check {
+    if !state.config.listener_mode.serves_https() {
+        return Check::pass(json!({ "detail": "HTTPS listener disabled" }));
+    }
+
+    let cert = state.cert.read().unwrap().clone();
+    let Some(cert) = cert else {
+        return Check::fail(json!({ "error": "no certificate loaded" }));
+    };
+
+    let days_remaining = cert.days_remaining();
+    let mut detail = json!({
+        "subject": cert.subject,
+        "not_before": cert.not_before.to_string(),
+        "not_after": cert.not_after.to_string(),
+        "days_remaining": days_remaining,
+        "fingerprint": cert.fingerprint,
+    });
+    if days_remaining < 0 {
+        detail["error"] = Value::String("certificate has expired".to_string());
+        Check::fail(detail)
+    } else if days_remaining < state.config.cert_expiry_warn_days {
+        detail["error"] = Value::String(format!(
+            "certificate expires within {} days",
+            state.config.cert_expiry_warn_days
+        ));
+        Check::fail(detail)
+    } else {
+        Check::pass(detail)
+    }
+}
+
+// Conversion workers still have room for new tasks
+fn check_queue(queue: &ConversionQueue) -> Check {
+    let detail = json!({
+        "workers": queue.workers(),
+        "running": queue.running(),
+        "pending": queue.pending(),
+        "max_pending": queue.max_pending(),
+    });
+    if queue.is_saturated() {
+        let mut detail = detail;
+        detail["error"] = Value::String("conversion queue is saturated".to_string());
+        Check::fail(detail)
+    } else {
+        Check::pass(detail)
+    }
+}
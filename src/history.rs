@@ -0,0 +1,651 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Html,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fs, path::PathBuf};
+use time::{Date, Month, OffsetDateTime};
+
+use crate::{
+    client_auth::ClientIdentity,
+    config::DOWNLOAD_DIR,
+    tasks::{ArtifactKind, TaskRecord, TaskStatus},
+    thumbnails, AppState,
+};
+
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+// Filters, sorting and pagination shared by the listing API and the history page
+//
+// Empty values are ignored so the page's filter form can submit every field.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TaskQuery {
+    // processing, completed or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    // Submission date range, `YYYY-MM-DD` or a unix timestamp, both inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    // Case-insensitive text the title must contain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    q: Option<String>,
+    // created_at (default), title or status
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort: Option<String>,
+    // asc or desc (default)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_page: Option<usize>,
+}
+
+// One page of matching tasks
+struct TaskPage {
+    tasks: Vec<(String, TaskRecord)>,
+    page: usize,
+    per_page: usize,
+    total: usize,
+}
+
+impl TaskPage {
+    fn total_pages(&self) -> usize {
+        self.total.div_ceil(self.per_page).max(1)
+    }
+}
+
+// Trimmed parameter value, None when missing or empty
+fn param(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn search(
+    state: &AppState,
+    identity: &ClientIdentity,
+    query: &TaskQuery,
+) -> Result<TaskPage, String> {
+    let status = match param(&query.status) {
+        Some(status @ ("processing" | "completed" | "failed")) => Some(status),
+        Some(other) => return Err(format!("Unknown status filter: {}", other)),
+        None => None,
+    };
+    let from = param(&query.from)
+        .map(|value| parse_bound(value, false))
+        .transpose()?;
+    let to = param(&query.to)
+        .map(|value| parse_bound(value, true))
+        .transpose()?;
+    let text = param(&query.q).map(str::to_lowercase);
+    let descending = match param(&query.order) {
+        None | Some("desc") => true,
+        Some("asc") => false,
+        Some(other) => return Err(format!("Unknown sort order: {}", other)),
+    };
+    let sort = param(&query.sort).unwrap_or("created_at");
+    if !matches!(sort, "created_at" | "title" | "status") {
+        return Err(format!("Unknown sort field: {}", sort));
+    }
+
+    let mut tasks: Vec<(String, TaskRecord)> = state
+        .tasks
+        .list()
+        .into_iter()
+        .filter(|(_, record)| identity.may_access(record.owner.as_deref()))
+        .filter(|(_, record)| status.is_none_or(|status| status_name(&record.status) == status))
+        .filter(|(_, record)| from.is_none_or(|from| record.created_at >= from))
+        .filter(|(_, record)| to.is_none_or(|to| record.created_at <= to))
+        .filter(|(_, record)| {
+            text.as_ref().is_none_or(|text| {
+                record
+                    .title
+                    .as_ref()
+                    .is_some_and(|title| title.to_lowercase().contains(text.as_str()))
+            })
+        })
+        .collect();
+
+    tasks.sort_by(|(a_id, a), (b_id, b)| {
+        let ordering = match sort {
+            "title" => display_title(a)
+                .to_lowercase()
+                .cmp(&display_title(b).to_lowercase()),
+            "status" => status_name(&a.status).cmp(status_name(&b.status)),
+            _ => a.created_at.cmp(&b.created_at),
+        };
+        let ordering = ordering
+            .then_with(|| a.created_at.cmp(&b.created_at))
+            .then_with(|| a_id.cmp(b_id));
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let page = query.page.unwrap_or(1).max(1);
+    let total = tasks.len();
+    let tasks = tasks
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+    Ok(TaskPage {
+        tasks,
+        page,
+        per_page,
+        total,
+    })
+}
+
+// Unix timestamp of a range bound; dates cover the whole day in UTC
+fn parse_bound(value: &str, end_of_day: bool) -> Result<u64, String> {
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+
+    let invalid = || format!("Invalid date: {} (expected YYYY-MM-DD)", value);
+    let mut parts = value.splitn(3, '-');
+    let mut next = || {
+        parts
+            .next()
+            .and_then(|part| part.parse::<i32>().ok())
+            .ok_or_else(invalid)
+    };
+    let (year, month, day) = (next()?, next()?, next()?);
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|month| Month::try_from(month).ok())
+        .ok_or_else(invalid)?;
+    let day = u8::try_from(day).map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(year, month, day).map_err(|_| invalid())?;
+
+    let start = date.midnight().assume_utc().unix_timestamp().max(0) as u64;
+    Ok(if end_of_day { start + 86_399 } else { start })
+}
+
+fn status_name(status: &TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Processing => "processing",
+        TaskStatus::Completed(..) => "completed",
+        TaskStatus::Failed(_) => "failed",
+    }
+}
+
+// Title, or the source URL while the title is unknown
+pub fn display_title(record: &TaskRecord) -> &str {
+    record
+        .title
+        .as_deref()
+        .or(record.url.as_deref())
+        .unwrap_or("Untitled")
+}
+
+pub fn task_json(task_id: &str, record: &TaskRecord) -> Value {
+    let mut task = json!({
+        "task_id": task_id,
+        "status": status_name(&record.status),
+        "title": record.title,
+        "url": record.url,
+        "created_at": record.created_at,
+        "finished_at": record.finished_at,
+    });
+    match &record.status {
+        TaskStatus::Completed(filename, thumbnail) => {
+            task["filename"] = json!(filename);
+            task["thumbnail"] = json!(thumbnail);
+        }
+        TaskStatus::Failed(error) => {
+            task["error"] = json!(error);
+            if let Some(code) = record.error_code {
+                task["error_code"] = json!(code);
+                task["retryable"] = json!(code.retryable());
+            }
+        }
+        TaskStatus::Processing => {}
+    }
+    if !record.artifacts.is_empty() {
+        task["artifacts"] = json!(record.artifacts);
+    }
+    if let Some(loudness) = &record.loudness {
+        task["loudness"] = json!(loudness);
+    }
+    if !record.attempts.is_empty() {
+        task["attempts"] = json!(record.attempts);
+    }
+    task
+}
+
+// GET /api/v1/tasks
+pub async fn list_tasks(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Query(query): Query<TaskQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let page = search(&state, &identity, &query)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))))?;
+    let tasks: Vec<Value> = page
+        .tasks
+        .iter()
+        .map(|(id, record)| task_json(id, record))
+        .collect();
+    Ok(Json(json!({
+        "tasks": tasks,
+        "page": page.page,
+        "per_page": page.per_page,
+        "total": page.total,
+        "total_pages": page.total_pages(),
+    })))
+}
+
+// DELETE /api/v1/tasks/:task_id, removing its files unless another task uses them
+pub async fn delete_task(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(task_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let record = state
+        .tasks
+        .get(&task_id)
+        .filter(|record| identity.may_access(record.owner.as_deref()))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "status": "not_found", "error": "Task not found" })),
+            )
+        })?;
+    if matches!(record.status, TaskStatus::Processing) {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "Task is still processing" })),
+        ));
+    }
+
+    state.tasks.remove(&task_id);
+    for filename in record.files() {
+        if !state.tasks.file_in_use(filename) {
+            let _ = fs::remove_file(PathBuf::from(DOWNLOAD_DIR).join(filename));
+        }
+    }
+    if let TaskStatus::Completed(_, Some(thumbnail)) = &record.status {
+        if !state.tasks.file_in_use(thumbnail) {
+            thumbnails::remove_variants(thumbnail);
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Server-rendered list of past conversions
+pub async fn history_page(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Query(query): Query<TaskQuery>,
+) -> Html<String> {
+    let (rows, pagination, error) = match search(&state, &identity, &query) {
+        Ok(page) => {
+            let rows: String = page
+                .tasks
+                .iter()
+                .map(|(id, record)| render_row(id, record))
+                .collect();
+            let rows = if rows.is_empty() {
+                r#"<p class="empty">No conversions found.</p>"#.to_string()
+            } else {
+                rows
+            };
+            (rows, render_pagination(&query, &page), String::new())
+        }
+        Err(e) => (
+            String::new(),
+            String::new(),
+            format!(r#"<p class="error">{}</p>"#, escape(&e)),
+        ),
+    };
+
+    let selected = |value: Option<&str>, option: &str| {
+        if value == Some(option) {
+            " selected"
+        } else {
+            ""
+        }
+    };
+    let status = param(&query.status);
+    let sort = param(&query.sort);
+    let order = param(&query.order);
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Conversion History</title>
+    <style>
+        * {{ margin: 0; padding: 0; box-sizing: border-box; }}
+        body {{
+            font-family: 'Segoe UI', Tahoma, Geneva, Verdana, sans-serif;
+            background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+            min-height: 100vh;
+            padding: 2rem 0;
+        }}
+        .container {{
+            background: white;
+            padding: 2rem;
+            border-radius: 15px;
+            box-shadow: 0 20px 40px rgba(0,0,0,0.1);
+            max-width: 900px;
+            width: 90%;
+            margin: 0 auto;
+        }}
+        h1 {{ color: #333; margin-bottom: 1rem; font-size: 2rem; }}
+        a {{ color: #667eea; }}
+        .filters {{ display: flex; flex-wrap: wrap; gap: 0.5rem; margin: 1rem 0 1.5rem; }}
+        .filters input, .filters select {{ padding: 8px; border: 2px solid #ddd; border-radius: 8px; font-size: 14px; }}
+        .filters input[type="search"] {{ flex: 1; min-width: 180px; }}
+        button {{
+            padding: 8px 14px;
+            background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+            color: white;
+            border: none;
+            border-radius: 8px;
+            font-weight: 600;
+            cursor: pointer;
+        }}
+        button:disabled {{ opacity: 0.6; cursor: not-allowed; }}
+        button.delete {{ background: #dc3545; }}
+        .task {{ display: flex; gap: 1rem; align-items: center; padding: 0.75rem 0; border-bottom: 1px solid #eee; }}
+        .task img, .task .no-thumb {{ width: 96px; height: 72px; object-fit: cover; border-radius: 8px; background: #f3f3f3; flex-shrink: 0; }}
+        .task .info {{ flex: 1; min-width: 0; }}
+        .task .title {{ font-weight: 600; color: #333; overflow-wrap: anywhere; }}
+        .task .meta {{ color: #777; font-size: 0.85rem; margin-top: 0.25rem; }}
+        .task .error {{ color: #721c24; font-size: 0.85rem; margin-top: 0.25rem; }}
+        .task .actions {{ display: flex; gap: 0.5rem; flex-shrink: 0; }}
+        .download-link {{
+            padding: 8px 14px;
+            background-color: #28a745;
+            color: white;
+            text-decoration: none;
+            border-radius: 8px;
+            font-weight: 500;
+        }}
+        .badge {{ display: inline-block; padding: 1px 8px; border-radius: 10px; font-size: 0.75rem; }}
+        .badge.processing {{ background-color: #fff3cd; color: #856404; }}
+        .badge.completed {{ background-color: #d4edda; color: #155724; }}
+        .badge.failed {{ background-color: #f8d7da; color: #721c24; }}
+        .pagination {{ display: flex; justify-content: space-between; align-items: center; margin-top: 1.5rem; color: #555; }}
+        #feeds h2 {{ font-size: 1.1rem; margin-top: 2rem; color: #333; }}
+        .empty, p.error {{ margin: 1rem 0; color: #555; }}
+        p.error {{ color: #721c24; }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>📜 Conversion History</h1>
+        <a href="/">← Back to converter</a>
+        <form class="filters" method="get" action="/history">
+            <input type="search" name="q" placeholder="Search titles..." value="{q}">
+            <select name="status">
+                <option value="">All statuses</option>
+                <option value="completed"{completed}>Completed</option>
+                <option value="failed"{failed}>Failed</option>
+                <option value="processing"{processing}>Processing</option>
+            </select>
+            <input type="date" name="from" value="{from}" title="Submitted from">
+            <input type="date" name="to" value="{to}" title="Submitted until">
+            <select name="sort">
+                <option value="created_at"{sort_created}>Date</option>
+                <option value="title"{sort_title}>Title</option>
+                <option value="status"{sort_status}>Status</option>
+            </select>
+            <select name="order">
+                <option value="desc"{desc}>Newest / Z–A first</option>
+                <option value="asc"{asc}>Oldest / A–Z first</option>
+            </select>
+            <button type="submit">Filter</button>
+        </form>
+        {error}
+        <div id="tasks">{rows}</div>
+        {pagination}
+        <div id="feeds"></div>
+    </div>
+    <script src="/player.js"></script>
+    <script>
+        // Podcast feeds of the listed conversions
+        fetch('/api/v1/feeds')
+            .then((response) => response.json())
+            .then((result) => {{
+                if (!result.feeds || result.feeds.length === 0) return;
+                const links = result.feeds.map((feed) => {{
+                    const link = document.createElement('li');
+                    const anchor = document.createElement('a');
+                    anchor.href = feed.url;
+                    anchor.textContent = feed.title;
+                    link.append(anchor, ` (${{feed.items}} episodes)`);
+                    return link;
+                }});
+                const list = document.createElement('ul');
+                list.append(...links);
+                const heading = document.createElement('h2');
+                heading.textContent = '🎙️ Podcast feeds';
+                document.getElementById('feeds').append(heading, list);
+            }})
+            .catch(() => {{}});
+
+        document.querySelectorAll('[data-preview]').forEach((button) => {{
+            button.addEventListener('click', () => {{
+                const info = button.closest('.task').querySelector('.info');
+                if (!info.querySelector('.player')) {{
+                    createPlayer(info, JSON.parse(button.dataset.preview));
+                }}
+                button.remove();
+            }});
+        }});
+
+        document.querySelectorAll('[data-retry-task]').forEach((button) => {{
+            button.addEventListener('click', async () => {{
+                button.disabled = true;
+                const response = await fetch(`/tasks/${{button.dataset.retryTask}}/retry`, {{ method: 'POST' }});
+                if (response.ok) {{
+                    location.reload();
+                }} else {{
+                    const result = await response.json().catch(() => ({{}}));
+                    alert(`❌ ${{result.error || 'Retry failed'}}`);
+                    button.disabled = false;
+                }}
+            }});
+        }});
+
+        document.querySelectorAll('[data-delete]').forEach((button) => {{
+            button.addEventListener('click', async () => {{
+                if (!confirm('Delete this conversion and its files?')) return;
+                button.disabled = true;
+                const response = await fetch(`/api/v1/tasks/${{button.dataset.delete}}`, {{ method: 'DELETE' }});
+                if (response.ok) {{
+                    button.closest('.task').remove();
+                }} else {{
+                    const result = await response.json().catch(() => ({{}}));
+                    alert(`❌ ${{result.error || 'Delete failed'}}`);
+                    button.disabled = false;
+                }}
+            }});
+        }});
+    </script>
+</body>
+</html>
+"#,
+        q = escape(param(&query.q).unwrap_or("")),
+        completed = selected(status, "completed"),
+        failed = selected(status, "failed"),
+        processing = selected(status, "processing"),
+        from = escape(param(&query.from).unwrap_or("")),
+        to = escape(param(&query.to).unwrap_or("")),
+        sort_created = selected(sort, "created_at"),
+        sort_title = selected(sort, "title"),
+        sort_status = selected(sort, "status"),
+        desc = selected(order, "desc"),
+        asc = selected(order, "asc"),
+        error = error,
+        rows = rows,
+        pagination = pagination,
+    ))
+}
+
+fn render_row(task_id: &str, record: &TaskRecord) -> String {
+    let status = status_name(&record.status);
+    let thumbnail = match &record.status {
+        TaskStatus::Completed(_, Some(thumbnail)) => format!(
+            r#"<img src="/thumbnail/{}?size=128" alt="" loading="lazy">"#,
+            escape(&url_path_segment(thumbnail))
+        ),
+        _ => r#"<div class="no-thumb"></div>"#.to_string(),
+    };
+
+    let mut actions = String::new();
+    if let TaskStatus::Completed(filename, _) = &record.status {
+        actions.push_str(&format!(
+            r#"<a class="download-link" href="/download/{}" download>📥 Download</a>"#,
+            escape(&url_path_segment(filename))
+        ));
+    }
+    if let Some(previews) = previews(record) {
+        actions.push_str(&format!(
+            r#"<button data-preview="{}">▶️ Preview</button>"#,
+            escape(&previews.to_string())
+        ));
+    }
+    if status != "processing" && record.url.is_some() {
+        // Failed tasks are converted again in place, keeping their attempts;
+        // completed ones as a new task with the same options
+        actions.push_str(&format!(
+            r#"<button data-retry-task="{}">🔄 Retry</button>"#,
+            escape(task_id)
+        ));
+    }
+    if status != "processing" {
+        actions.push_str(&format!(
+            r#"<button class="delete" data-delete="{}">🗑️ Delete</button>"#,
+            escape(task_id)
+        ));
+    }
+
+    let error = match &record.status {
+        TaskStatus::Failed(error) => format!(r#"<div class="error">{}</div>"#, escape(error)),
+        _ => String::new(),
+    };
+
+    format!(
+        r#"<div class="task">{thumbnail}<div class="info"><div class="title">{title}</div><div class="meta"><span class="badge {status}">{status}</span> · {created}</div>{error}</div><div class="actions">{actions}</div></div>"#,
+        thumbnail = thumbnail,
+        title = escape(display_title(record)),
+        status = status,
+        created = format_timestamp(record.created_at),
+        error = error,
+        actions = actions,
+    )
+}
+
+// Tracks of a split conversion, or its MP3, for the player
+fn previews(record: &TaskRecord) -> Option<Value> {
+    let TaskStatus::Completed(filename, _) = &record.status else {
+        return None;
+    };
+    let tracks: Vec<Value> = record
+        .artifacts
+        .iter()
+        .filter(|artifact| artifact.kind == ArtifactKind::Track)
+        .map(|track| json!({ "filename": track.filename, "title": track.title }))
+        .collect();
+    if !tracks.is_empty() {
+        Some(json!(tracks))
+    } else if filename.ends_with(".mp3") {
+        Some(json!([{ "filename": filename, "title": display_title(record) }]))
+    } else {
+        None
+    }
+}
+
+fn render_pagination(query: &TaskQuery, page: &TaskPage) -> String {
+    let link = |target: usize, label: &str| {
+        // Drop the empty fields the filter form submits
+        let query = TaskQuery {
+            status: param(&query.status).map(str::to_string),
+            from: param(&query.from).map(str::to_string),
+            to: param(&query.to).map(str::to_string),
+            q: param(&query.q).map(str::to_string),
+            sort: param(&query.sort).map(str::to_string),
+            order: param(&query.order).map(str::to_string),
+            page: Some(target),
+            per_page: query.per_page,
+        };
+        let query = serde_urlencoded::to_string(&query).unwrap_or_default();
+        format!(r#"<a href="/history?{}">{}</a>"#, escape(&query), label)
+    };
+
+    let previous = if page.page > 1 {
+        link(page.page - 1, "← Previous")
+    } else {
+        String::new()
+    };
+    let next = if page.page < page.total_pages() {
+        link(page.page + 1, "Next →")
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<div class="pagination"><span>{}</span><span>Page {} of {} · {} conversions</span><span>{}</span></div>"#,
+        previous,
+        page.page,
+        page.total_pages(),
+        page.total,
+        next
+    )
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    match OffsetDateTime::from_unix_timestamp(timestamp as i64) {
+        Ok(time) if timestamp > 0 => format!(
+            "{}-{:02}-{:02} {:02}:{:02} UTC",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute()
+        ),
+        _ => "unknown date".to_string(),
+    }
+}
+
+// Percent-encode a file name for use in a URL path
+pub fn url_path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
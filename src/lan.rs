@@ -0,0 +1,88 @@
+use std::net::IpAddr;
+
+// Interface name filter for LAN address detection
+//
+// Patterns match an interface name exactly, or by prefix when they end
+// with `*` (e.g. `docker*`). An empty allow list allows every interface.
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl InterfaceFilter {
+    pub fn permits(&self, name: &str) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|p| matches_pattern(p, name));
+        allowed && !self.deny.iter().any(|p| matches_pattern(p, name))
+    }
+}
+
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+// Names and addresses other devices on the network can use to reach this machine
+#[derive(Debug, Clone, Default)]
+pub struct LanIdentity {
+    pub hostnames: Vec<String>,
+    pub addresses: Vec<IpAddr>,
+}
+
+// Enumerate non-loopback interface addresses and the machine's hostname
+//
+// IPv6 link-local addresses are skipped because browsers cannot use them
+// without a zone index. The hostname is also offered with `.local` for mDNS.
+pub fn detect(filter: &InterfaceFilter) -> LanIdentity {
+    let mut identity = LanIdentity::default();
+
+    match if_addrs::get_if_addrs() {
+        Ok(interfaces) => {
+            for interface in interfaces {
+                if interface.is_loopback() || !filter.permits(&interface.name) {
+                    continue;
+                }
+                let ip = interface.ip();
+                if is_link_local(&ip) || identity.addresses.contains(&ip) {
+                    continue;
+                }
+                identity.addresses.push(ip);
+            }
+        }
+        Err(e) => eprintln!("⚠️  Cannot list network interfaces: {}", e),
+    }
+
+    if let Some(name) = hostname::get().ok().and_then(|h| h.into_string().ok()) {
+        let name = name.trim().trim_end_matches('.').to_ascii_lowercase();
+        if is_dns_name(&name) && name != "localhost" {
+            if !name.contains('.') {
+                identity.hostnames.push(format!("{}.local", name));
+            }
+            identity.hostnames.insert(0, name);
+        }
+    }
+
+    identity
+}
+
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+// Hostnames like "DESKTOP_42" are not valid in certificates
+fn is_dns_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
@@ -0,0 +1,3 @@
+// Code shared between the server and the cert-gen tool
+pub mod certs;
+pub mod lan;
@@ -0,0 +1,357 @@
+use serde::Deserialize;
+use std::{fs, path::Path, process::Stdio};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::Config,
+    credentials::Access,
+    failures::{Failure, FailureKind},
+    feeds,
+};
+
+// Bytes per second of the MP3s, encoded at 192 kbps
+const MP3_BYTES_PER_SEC: f64 = 192_000.0 / 8.0;
+
+// Metadata yt-dlp reports without downloading
+#[derive(Debug, Default, Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    live_status: Option<String>,
+    #[serde(default)]
+    is_live: Option<bool>,
+    // Download size of the selected format, exact or estimated
+    #[serde(default)]
+    filesize: Option<u64>,
+    #[serde(default)]
+    filesize_approx: Option<u64>,
+    // Videos of a playlist URL
+    #[serde(default)]
+    entries: Option<Vec<Metadata>>,
+}
+
+impl Metadata {
+    fn is_live(&self) -> bool {
+        self.is_live == Some(true) || self.live_status.as_deref() == Some("is_live")
+    }
+
+    // Total length, a playlist's being the sum of its videos
+    fn duration(&self) -> Option<f64> {
+        match &self.entries {
+            Some(entries) => entries.iter().map(Metadata::duration).sum(),
+            None => self.duration,
+        }
+    }
+
+    fn download_size(&self) -> Option<u64> {
+        match &self.entries {
+            Some(entries) => entries.iter().map(Metadata::download_size).sum(),
+            None => self.filesize.or(self.filesize_approx),
+        }
+    }
+}
+
+// Longest video and largest file accepted, 0 for no limit
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_duration_secs: u64,
+    pub max_filesize_mb: u64,
+}
+
+impl Limits {
+    pub fn of(config: &Config) -> Self {
+        Limits {
+            max_duration_secs: config.max_duration_secs,
+            max_filesize_mb: config.max_filesize_mb,
+        }
+    }
+
+    fn max_bytes(self) -> u64 {
+        self.max_filesize_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+// Log the limits in effect; none applies unless configured
+pub fn report(config: &Config) {
+    let duration = |secs: u64| match secs {
+        0 => "unlimited".to_string(),
+        secs => feeds::duration(secs as f64),
+    };
+    let size = match config.max_filesize_mb {
+        0 => "unlimited".to_string(),
+        mb => format!("{} MB", mb),
+    };
+    println!(
+        "ℹ️  Limits: video length {}, size {}, conversion run time {}; set with YT_MP3_MAX_DURATION_SECS, YT_MP3_MAX_FILESIZE_MB and YT_MP3_TASK_TIMEOUT_SECS",
+        duration(config.max_duration_secs),
+        size,
+        duration(config.task_timeout_secs)
+    );
+}
+
+// Whether any limit is configured, so the pre-flight request is needed
+pub fn enabled(config: &Config) -> bool {
+    config.max_duration_secs > 0 || config.max_filesize_mb > 0
+}
+
+// Fetch the metadata of `url` and refuse it before downloading when it
+// exceeds the configured duration or size, or is a live stream
+//
+// The size is that of `format`, the source format the download will select.
+pub async fn preflight(
+    config: &Config,
+    url: &str,
+    format: Option<&str>,
+    access: &Access,
+    abort: &CancellationToken,
+) -> Result<(), Failure> {
+    let child = Command::new(&config.yt_dlp_path)
+        .args([
+            "--dump-single-json",
+            "--skip-download",
+            "--no-playlist",
+            "--flat-playlist",
+            "--format",
+            format.unwrap_or("bestaudio/best"),
+        ])
+        .args(access.args())
+        .arg(url)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            eprintln!("⚠️  Cannot execute {}: {}", config.yt_dlp_path.display(), e);
+            Failure::new(FailureKind::YtDlpMissing)
+        })?;
+    let output = tokio::select! {
+        output = child.wait_with_output() => output.map_err(|e| Failure::from(e.to_string()))?,
+        _ = abort.cancelled() => return Err(Failure::new(FailureKind::Cancelled)),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Failure::from_yt_dlp(&stderr));
+    }
+    let metadata: Metadata = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        eprintln!(
+            "⚠️  Unreadable metadata for {}, limits checked during download: {}",
+            url, e
+        );
+        Metadata::default()
+    });
+    check(Limits::of(config), &metadata)
+}
+
+fn check(limits: Limits, metadata: &Metadata) -> Result<(), Failure> {
+    if metadata.is_live() {
+        return Err(Failure::new(FailureKind::LiveStream));
+    }
+    let duration = metadata.duration();
+    if let Some(duration) = duration.filter(|_| limits.max_duration_secs > 0) {
+        if duration > limits.max_duration_secs as f64 {
+            return Err(duration_failure(duration, limits));
+        }
+    }
+    if limits.max_filesize_mb > 0 {
+        // The MP3 is sized by its bitrate, the download by the source format
+        let size = duration
+            .map(|duration| (duration * MP3_BYTES_PER_SEC) as u64)
+            .max(metadata.download_size());
+        if let Some(size) = size.filter(|&size| size > limits.max_bytes()) {
+            return Err(size_failure(size, limits));
+        }
+    }
+    Ok(())
+}
+
+// yt-dlp arguments enforcing the limits while downloading, for sources
+// whose metadata did not tell
+pub fn yt_dlp_args(limits: Limits) -> Vec<String> {
+    let mut args = Vec::new();
+    if limits.max_filesize_mb > 0 {
+        args.push("--max-filesize".to_string());
+        args.push(format!("{}M", limits.max_filesize_mb));
+    }
+    if limits.max_duration_secs > 0 {
+        // `<=?` lets videos without a known duration through
+        args.push("--match-filter".to_string());
+        args.push(format!(
+            "!is_live & duration <=? {}",
+            limits.max_duration_secs
+        ));
+    }
+    args
+}
+
+// yt-dlp skips videos over the limits without failing; its output tells
+pub fn rejection(stdout: &str, limits: Limits) -> Option<Failure> {
+    if stdout.contains("larger than max-filesize") {
+        Some(Failure {
+            message: format!(
+                "This video is larger than the limit of {} MB.",
+                limits.max_filesize_mb
+            ),
+            ..Failure::new(FailureKind::SizeLimit)
+        })
+    } else if stdout.contains("does not pass filter") {
+        Some(Failure {
+            message: format!(
+                "This video is longer than the limit of {}, or is a live stream.",
+                feeds::duration(limits.max_duration_secs as f64)
+            ),
+            ..Failure::new(FailureKind::DurationLimit)
+        })
+    } else {
+        None
+    }
+}
+
+// Refuse a converted file in `dir` over the size limit, removing it
+pub fn check_output(limits: Limits, dir: &Path, filename: &str) -> Result<(), Failure> {
+    if limits.max_filesize_mb == 0 {
+        return Ok(());
+    }
+    let path = dir.join(filename);
+    let size = fs::metadata(&path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if size > limits.max_bytes() {
+        let _ = fs::remove_file(&path);
+        return Err(size_failure(size, limits));
+    }
+    Ok(())
+}
+
+fn duration_failure(duration: f64, limits: Limits) -> Failure {
+    Failure {
+        message: format!(
+            "This video is {} long, longer than the limit of {}.",
+            feeds::duration(duration),
+            feeds::duration(limits.max_duration_secs as f64)
+        ),
+        ..Failure::new(FailureKind::DurationLimit)
+    }
+}
+
+fn size_failure(size: u64, limits: Limits) -> Failure {
+    Failure {
+        message: format!(
+            "This video would take about {} MB, more than the limit of {} MB.",
+            size.div_ceil(1024 * 1024),
+            limits.max_filesize_mb
+        ),
+        ..Failure::new(FailureKind::SizeLimit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_duration_secs: u64, max_filesize_mb: u64) -> Limits {
+        Limits {
+            max_duration_secs,
+            max_filesize_mb,
+        }
+    }
+
+    fn video(duration: Option<f64>, filesize: Option<u64>) -> Metadata {
+        Metadata {
+            duration,
+            filesize,
+            ..Metadata::default()
+        }
+    }
+
+    fn kind(result: Result<(), Failure>) -> Option<FailureKind> {
+        result.err().map(|failure| failure.kind)
+    }
+
+    #[test]
+    fn check_refuses_long_and_live_videos() {
+        let limits = limits(3600, 0);
+        assert_eq!(kind(check(limits, &video(Some(3600.0), None))), None);
+        assert_eq!(
+            kind(check(limits, &video(Some(3600.5), None))),
+            Some(FailureKind::DurationLimit)
+        );
+        // Unknown durations are left to the yt-dlp filter
+        assert_eq!(kind(check(limits, &video(None, None))), None);
+        let live = Metadata {
+            live_status: Some("is_live".to_string()),
+            ..Metadata::default()
+        };
+        assert_eq!(kind(check(limits, &live)), Some(FailureKind::LiveStream));
+        // Live streams are refused even without limits
+        assert_eq!(
+            kind(check(self::limits(0, 0), &live)),
+            Some(FailureKind::LiveStream)
+        );
+    }
+
+    #[test]
+    fn check_sizes_by_mp3_bitrate_and_download() {
+        let limits = limits(0, 10);
+        // 10 MB of MP3 at 192 kbps last about 437 seconds
+        assert_eq!(kind(check(limits, &video(Some(430.0), None))), None);
+        assert_eq!(
+            kind(check(limits, &video(Some(450.0), None))),
+            Some(FailureKind::SizeLimit)
+        );
+        assert_eq!(
+            kind(check(limits, &video(Some(60.0), Some(11 * 1024 * 1024)))),
+            Some(FailureKind::SizeLimit)
+        );
+        let approx = Metadata {
+            filesize_approx: Some(11 * 1024 * 1024),
+            ..Metadata::default()
+        };
+        assert_eq!(kind(check(limits, &approx)), Some(FailureKind::SizeLimit));
+    }
+
+    #[test]
+    fn check_adds_up_playlists() {
+        let playlist = Metadata {
+            entries: Some(vec![video(Some(2000.0), None), video(Some(2000.0), None)]),
+            ..Metadata::default()
+        };
+        assert_eq!(
+            kind(check(limits(3600, 0), &playlist)),
+            Some(FailureKind::DurationLimit)
+        );
+        assert_eq!(kind(check(limits(0, 0), &playlist)), None);
+    }
+
+    #[test]
+    fn yt_dlp_args_follow_the_limits() {
+        assert!(yt_dlp_args(limits(0, 0)).is_empty());
+        assert_eq!(
+            yt_dlp_args(limits(600, 20)),
+            [
+                "--max-filesize",
+                "20M",
+                "--match-filter",
+                "!is_live & duration <=? 600"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejection_reads_skipped_downloads() {
+        let limits = limits(600, 20);
+        let skipped = "[download] abc: has already been recorded\n\
+                       [download] File is larger than max-filesize (30000000 bytes > 20971520 bytes). Aborting.";
+        assert_eq!(
+            rejection(skipped, limits).map(|failure| failure.kind),
+            Some(FailureKind::SizeLimit)
+        );
+        let filtered = "[download] Long video does not pass filter (duration <=? 600), skipping ..";
+        assert_eq!(
+            rejection(filtered, limits).map(|failure| failure.kind),
+            Some(FailureKind::DurationLimit)
+        );
+        assert!(rejection("[download] 100% of 3.50MiB", limits).is_none());
+    }
+}
@@ -0,0 +1,65 @@
+use axum::{
+    extract::{Host, State},
+    http::{header, HeaderValue, Uri},
+    response::Redirect,
+    routing::get,
+    Router,
+};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::{acme, config::Config, health, AppState};
+
+// App served on the plain HTTP listener in redirect mode
+//
+// Health probes and ACME HTTP-01 challenges stay reachable over HTTP;
+// everything else is sent to HTTPS.
+pub fn redirect_app(state: AppState) -> Router {
+    Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/.well-known/acme-challenge/:token", get(acme::http_challenge))
+        .fallback(redirect_to_https)
+        .with_state(state)
+}
+
+// 308 keeps the method and body, so form posts survive the redirect
+async fn redirect_to_https(State(state): State<AppState>, Host(host): Host, uri: Uri) -> Redirect {
+    let host = strip_port(&host);
+    let port = state.config.public_https_port;
+    let authority = if port == 443 {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    Redirect::permanent(&format!("https://{}{}", authority, path))
+}
+
+// Remove the port from a Host header value, keeping IPv6 brackets
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host,
+        }
+    } else {
+        host.split(':').next().unwrap_or(host)
+    }
+}
+
+// Add `Strict-Transport-Security` to HTTPS responses when enabled
+pub fn with_hsts(app: Router, config: &Config) -> Router {
+    if config.hsts_max_age == 0 {
+        return app;
+    }
+
+    let mut value = format!("max-age={}", config.hsts_max_age);
+    if config.hsts_include_subdomains {
+        value.push_str("; includeSubDomains");
+    }
+    let value = HeaderValue::from_str(&value).expect("HSTS header value is valid ASCII");
+    app.layer(SetResponseHeaderLayer::if_not_present(
+        header::STRICT_TRANSPORT_SECURITY,
+        value,
+    ))
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, process::Stdio};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::Config,
+    failures::{Failure, FailureKind},
+};
+
+// Loudness range target; only integrated loudness and true peak are configurable
+const LOUDNESS_RANGE: f64 = 11.0;
+
+// Measurements of a two-pass EBU R128 normalization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loudness {
+    // Integrated loudness target in LUFS and true-peak ceiling in dBTP
+    pub target_i: f64,
+    pub target_tp: f64,
+    pub input: Measurement,
+    pub output: Measurement,
+    // `linear` keeps the dynamics, `dynamic` was needed to respect the ceiling
+    pub normalization_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Measurement {
+    // Integrated loudness in LUFS
+    pub integrated: f64,
+    // True peak in dBTP
+    pub true_peak: f64,
+    // Loudness range in LU
+    pub lra: f64,
+}
+
+// JSON block printed by ffmpeg's loudnorm filter; numbers are strings
+#[derive(Deserialize)]
+struct LoudnormStats {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    output_i: String,
+    output_tp: String,
+    output_lra: String,
+    target_offset: String,
+    #[serde(default)]
+    normalization_type: String,
+}
+
+// Measure the file in `dir`, then rewrite it at the configured loudness
+//
+// Returns None for silent audio, which has no loudness to normalize.
+pub async fn normalize(
+    config: &Config,
+    dir: &Path,
+    audio_filename: &str,
+    abort: &CancellationToken,
+) -> Result<Option<Loudness>, Failure> {
+    let input = dir.join(audio_filename);
+    let (target_i, target_tp) = (config.loudnorm_target, config.loudnorm_true_peak);
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}",
+        target_i, target_tp, LOUDNESS_RANGE
+    );
+
+    // First pass: analysis only
+    let mut command = Command::new(&config.ffmpeg_path);
+    command
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(&input)
+        .args(["-af", &format!("{}:print_format=json", filter)])
+        .args(["-f", "null", "-"]);
+    let measured = run(command, abort).await?;
+    let input_i = number(&measured.input_i)?;
+    if !input_i.is_finite() {
+        return Ok(None);
+    }
+
+    // Second pass: apply the measured values so the gain is linear where possible
+    let stem = audio_filename
+        .strip_suffix(".mp3")
+        .unwrap_or(audio_filename);
+    let normalized = dir.join(format!("{}.loudnorm.mp3", stem));
+    let mut command = Command::new(&config.ffmpeg_path);
+    command
+        .args(["-hide_banner", "-nostats", "-y", "-i"])
+        .arg(&input)
+        .args(["-map", "0:a", "-map_metadata", "0", "-id3v2_version", "3"])
+        .args([
+            "-af",
+            &format!(
+                "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true:print_format=json",
+                filter,
+                measured.input_i,
+                measured.input_tp,
+                measured.input_lra,
+                measured.input_thresh,
+                measured.target_offset
+            ),
+        ])
+        // loudnorm resamples to 192 kHz internally
+        .args(["-ar", "44100", "-c:a", "libmp3lame", "-b:a", "192k"])
+        .arg(&normalized);
+    let applied = match run(command, abort).await {
+        Ok(applied) => applied,
+        Err(e) => {
+            let _ = fs::remove_file(&normalized);
+            return Err(e);
+        }
+    };
+    fs::rename(&normalized, &input).map_err(|e| {
+        let _ = fs::remove_file(&normalized);
+        format!("Cannot replace {}: {}", audio_filename, e)
+    })?;
+
+    Ok(Some(Loudness {
+        target_i,
+        target_tp,
+        input: Measurement {
+            integrated: input_i,
+            true_peak: number(&measured.input_tp)?,
+            lra: number(&measured.input_lra)?,
+        },
+        output: Measurement {
+            integrated: number(&applied.output_i)?,
+            true_peak: number(&applied.output_tp)?,
+            lra: number(&applied.output_lra)?,
+        },
+        normalization_type: applied.normalization_type,
+    }))
+}
+
+// Run one ffmpeg pass and parse the loudnorm statistics from its log
+async fn run(mut command: Command, abort: &CancellationToken) -> Result<LoudnormStats, Failure> {
+    let child = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Cannot execute ffmpeg: {}", e))?;
+    let output = tokio::select! {
+        output = child.wait_with_output() => output.map_err(|e| e.to_string())?,
+        _ = abort.cancelled() => return Err(Failure::new(FailureKind::Cancelled)),
+    };
+
+    let log = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("Loudness normalization failed: {}", log.trim()).into());
+    }
+    Ok(stats(&log)?)
+}
+
+// The statistics are the last JSON object in the log
+fn stats(log: &str) -> Result<LoudnormStats, String> {
+    let json = log
+        .rfind('{')
+        .and_then(|start| {
+            log[start..]
+                .find('}')
+                .map(|end| &log[start..start + end + 1])
+        })
+        .ok_or_else(|| "Loudness normalization printed no statistics".to_string())?;
+    serde_json::from_str(json).map_err(|e| format!("Invalid loudness statistics: {}", e))
+}
+
+// Parse a loudnorm value, which may be `-inf` for silence
+fn number(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid loudness value: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"Input #0, mp3, from 'downloads/{odd} title_1.mp3':
+  Duration: 00:03:32.40, start: 0.025057, bitrate: 192 kb/s
+[Parsed_loudnorm_0 @ 0x5581c0e3c8c0]
+{
+	"input_i" : "-9.57",
+	"input_tp" : "0.34",
+	"input_lra" : "4.10",
+	"input_thresh" : "-19.71",
+	"output_i" : "-16.02",
+	"output_tp" : "-1.50",
+	"output_lra" : "3.90",
+	"output_thresh" : "-26.09",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.02"
+}
+[out#0/null @ 0x5581c0e1e440] video:0KiB audio:36568KiB
+"#;
+
+    #[test]
+    fn stats_are_read_from_the_last_json_object() {
+        let stats = stats(LOG).unwrap();
+        assert_eq!(stats.input_i, "-9.57");
+        assert_eq!(stats.input_thresh, "-19.71");
+        assert_eq!(stats.output_tp, "-1.50");
+        assert_eq!(stats.target_offset, "0.02");
+        assert_eq!(stats.normalization_type, "dynamic");
+    }
+
+    #[test]
+    fn stats_without_json_are_an_error() {
+        assert!(stats("").is_err());
+        assert!(stats("Error opening input file x.mp3.").is_err());
+        // A truncated log ends inside the object
+        assert!(stats(&LOG[..LOG.find("\"output_i\"").unwrap()]).is_err());
+    }
+
+    #[test]
+    fn number_reads_silence_as_negative_infinity() {
+        assert_eq!(number(" -23.5 "), Ok(-23.5));
+        assert_eq!(number("-inf"), Ok(f64::NEG_INFINITY));
+        assert!(!number("-inf").unwrap().is_finite());
+        assert!(number("").is_err());
+        assert!(number("loud").is_err());
+    }
+}
@@ -1,89 +1,352 @@
+mod acme;
+mod batch;
+mod chapters;
+mod cli;
+mod client_auth;
+mod config;
+mod credentials;
+mod failures;
+mod feeds;
+mod health;
+mod history;
+mod limits;
+mod listeners;
+mod loudness;
+mod queue;
+mod shutdown;
+mod subtitles;
+mod tasks;
+mod thumbnails;
+mod tls;
+mod tools;
+mod video_info;
+mod waveform;
+mod webhooks;
+
 use axum::{
-    extract::{Form, Path},
-    http::{header, StatusCode},
-    response::{Html, IntoResponse},
-    routing::{get, post},
+    extract::{Form, Path, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
     Router,
 };
-use axum_server::tls_rustls::RustlsConfig;
+use axum_server::{
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+    Handle,
+};
+use clap::Parser;
+use client_auth::{ClientCertAcceptor, ClientIdentity};
+use config::{AcmeChallenge, ClientAuth, Config, ListenerMode, DOWNLOAD_DIR, STATE_DIR};
+use failures::{Failure, FailureKind};
+use feeds::{BaseUrl, Feeds};
+use limits::Limits;
+use queue::ConversionQueue;
 use serde::Deserialize;
+use shutdown::Shutdown;
 use std::{
-    collections::HashMap,
     fs,
     path::PathBuf,
-    process::Command,
-    sync::{Arc, Mutex},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tower_http::services::ServeDir;
+use tasks::{Artifact, ArtifactKind, ConvertOptions, TaskRecord, TaskStatus, TaskStore};
+use tls::CertInfo;
+use tokio::{io::AsyncBufReadExt, process::Command};
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+use tower_http::services::{ServeDir, ServeFile};
 use uuid::Uuid;
+use video_info::VideoInfo;
+use webhooks::Webhooks;
+
+// Grace period for open connections once the listeners stop
+const LISTENER_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+// Shared application state
+#[derive(Clone)]
+struct AppState {
+    tasks: Arc<TaskStore>,
+    config: Arc<Config>,
+    queue: Arc<ConversionQueue>,
+    cert: Arc<RwLock<Option<CertInfo>>>,
+    acme: Arc<acme::Challenges>,
+    webhooks: Arc<Webhooks>,
+    feeds: Arc<Feeds>,
+    tool_checks: Arc<health::ToolChecks>,
+    shutdown: Shutdown,
+}
 
-// Task status enum
-#[derive(Debug, Clone)]
-enum TaskStatus {
-    Processing,
-    Completed(String, Option<String>), // Store audio file path and thumbnail file path
-    Failed(String),    // Store error message
+// Files and measurements produced by a conversion
+struct Conversion {
+    // Main download: the MP3, or the zip of a chapter split
+    audio: String,
+    thumbnail: Option<String>,
+    artifacts: Vec<Artifact>,
+    loudness: Option<loudness::Loudness>,
+    // Length of the video in seconds
+    duration: Option<f64>,
 }
 
-// Global task status management
-type TaskMap = Arc<Mutex<HashMap<String, TaskStatus>>>;
+// What a running conversion is doing, for the terminal of `yt-mp3 convert`
+enum Progress {
+    // A step of the pipeline has started
+    Step(&'static str),
+    // Percentage of the current download
+    Downloaded(f64),
+    // Something worth telling about the result
+    Note(String),
+}
+
+// The server only logs the notes
+fn log_progress(progress: Progress) {
+    if let Progress::Note(note) = progress {
+        println!("ℹ️  {}", note);
+    }
+}
 
 // Form data structure
 #[derive(Deserialize)]
 struct ConvertForm {
     youtube_url: String,
+    // Webhook notified when the conversion finishes
+    #[serde(default)]
+    callback_url: Option<String>,
+    // One track per chapter, bundled in a zip with an M3U playlist
+    #[serde(default)]
+    split_chapters: bool,
+    // EBU R128 loudness normalization
+    #[serde(default)]
+    normalize: bool,
+    // Crop the thumbnail to a square, as album art
+    #[serde(default)]
+    square_thumbnail: bool,
+    // Named podcast feed the result is published in
+    #[serde(default)]
+    collection: Option<String>,
+    // Comma-separated subtitle languages
+    #[serde(default)]
+    subtitles: Option<String>,
+    #[serde(default)]
+    auto_subtitles: bool,
+    #[serde(default)]
+    embed_lyrics: bool,
+    // Proxy for this conversion instead of the global one
+    #[serde(default)]
+    proxy: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
+    // `yt-mp3 convert <url>` converts on the terminal instead of serving
+    let cli = cli::Cli::parse();
+    if let Some(cli::Command::Convert(args)) = cli.command {
+        std::process::exit(cli::convert(args).await);
+    }
+
+    let config = Config::from_env();
+
     // Create download directory
-    let download_dir = "downloads";
-    if !std::path::Path::new(download_dir).exists() {
-        fs::create_dir_all(download_dir).expect("Failed to create download directory");
+    if !std::path::Path::new(DOWNLOAD_DIR).exists() {
+        fs::create_dir_all(DOWNLOAD_DIR).expect("Failed to create download directory");
+    }
+
+    // Report where yt-dlp and FFmpeg were found, and whether they are recent enough
+    tools::verify(&config).await;
+    limits::report(&config);
+
+    // Task records stay out of the served downloads directory, readable only by the server
+    if let Err(e) = credentials::create_private_dir(std::path::Path::new(STATE_DIR)) {
+        panic!("Failed to create state directory: {}", e);
     }
+    config::move_legacy_state();
 
-    // Initialize task status management
-    let tasks: TaskMap = Arc::new(Mutex::new(HashMap::new()));
+    // Initialize task status management, restoring tasks from the previous run
+    let tasks = Arc::new(TaskStore::load(PathBuf::from(STATE_DIR).join("tasks.json")));
+    let config = Arc::new(config);
+
+    let shutdown = Shutdown::new();
+    let state = AppState {
+        webhooks: Arc::new(Webhooks::new(config.clone(), tasks.clone(), shutdown.clone())),
+        feeds: Arc::new(Feeds::load(&config)),
+        tasks,
+        queue: Arc::new(ConversionQueue::new(
+            config.max_concurrent_conversions,
+            config.max_queued_conversions,
+        )),
+        cert: Arc::new(RwLock::new(None)),
+        acme: Arc::new(acme::Challenges::default()),
+        tool_checks: Arc::new(health::ToolChecks::default()),
+        shutdown,
+        config,
+    };
 
     // Setup routes
     let app = Router::new()
         .route("/", get(index_page))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/.well-known/acme-challenge/:token", get(acme::http_challenge))
         .route("/convert", post(convert_youtube))
+        .route("/batches", post(batch::create_batch))
+        .route("/batches/:batch_id", get(batch::batch_status))
         .route("/status/:task_id", get(check_status))
+        .route("/tasks/:task_id/retry", post(retry_task))
+        .route("/tasks/:task_id/webhooks", get(webhooks::history))
+        .route("/tasks/:task_id/webhooks/replay", post(webhooks::replay))
+        .route("/history", get(history::history_page))
+        .route("/api/v1/tasks", get(history::list_tasks))
+        .route("/api/v1/tasks/:task_id", delete(history::delete_task))
+        .route(
+            "/api/v1/cookies",
+            get(credentials::cookie_status)
+                .put(credentials::upload_cookies)
+                .delete(credentials::delete_cookies),
+        )
+        .route("/api/v1/feeds", get(feeds::list_feeds))
+        .route("/feeds/:feed_file", get(feeds::feed))
+        .route("/feeds/:feed_id/media/:filename", get(feeds::media))
         .route("/download/:filename", get(download_file))
-        .route("/thumbnail/:filename", get(serve_thumbnail))
+        .route("/stream/:filename", get(stream_file))
+        .route("/waveform/:filename", get(waveform::peaks))
+        .route("/player.js", get(waveform::player_script))
+        .route("/thumbnail/:filename", get(thumbnails::serve_thumbnail))
         .nest_service("/static", ServeDir::new("static"))
-        .with_state(tasks);
-
-    // Configure TLS
-    let config = RustlsConfig::from_pem_file(
-        PathBuf::from("certs/cert.pem"),
-        PathBuf::from("certs/key.pem"),
-    )
-    .await
-    .expect("Failed to load SSL certificate");
-    
-    println!("🚀 HTTPS server started at https://127.0.0.1:3443");
-    println!("🌐 HTTP server started at http://127.0.0.1:3000");
-    
-    // Start both HTTP and HTTPS servers
-    let app_clone = app.clone();
-    tokio::spawn(async move {
-        // HTTP server
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-            .await
-            .expect("Unable to bind to port 3000");
-        axum::serve(listener, app_clone)
+        .with_state(state.clone());
+
+    // Plain HTTP carries no client certificate, so serving the app on it would
+    // bypass required client authentication; it only redirects to HTTPS then
+    let mode = match (state.config.listener_mode, state.config.client_auth) {
+        (ListenerMode::Both, ClientAuth::Required) => {
+            println!("ℹ️  Client certificates are required, the HTTP listener only redirects to HTTPS");
+            ListenerMode::Redirect
+        }
+        (ListenerMode::HttpOnly, ClientAuth::Required) => {
+            panic!("YT_MP3_CLIENT_AUTH=required needs the HTTPS listener, which YT_MP3_LISTENER_MODE=http disables")
+        }
+        (mode, _) => mode,
+    };
+    if state.config.acme_directory.is_some() && state.config.acme_domains.is_empty() {
+        eprintln!("⚠️  YT_MP3_ACME_DIRECTORY is set but YT_MP3_ACME_DOMAINS is empty, ACME disabled");
+    }
+    if state.config.acme_enabled()
+        && state.config.acme_challenge == AcmeChallenge::Http01
+        && !mode.serves_http()
+    {
+        eprintln!("⚠️  ACME HTTP-01 challenges need the HTTP listener, which is disabled");
+    }
+
+    // HTTP server
+    let http_server = if mode.serves_http() {
+        let http_app = if mode == ListenerMode::Redirect {
+            listeners::redirect_app(state.clone())
+        } else {
+            app.clone()
+        };
+        let addr = state.config.http_addr;
+        let listener = tokio::net::TcpListener::bind(addr)
             .await
-            .expect("Failed to start HTTP server");
-    });
+            .unwrap_or_else(|e| panic!("Unable to bind to {}: {}", addr, e));
+        if mode == ListenerMode::Redirect {
+            println!("🌐 HTTP server started at http://{} (redirecting to HTTPS)", addr);
+        } else {
+            println!("🌐 HTTP server started at http://{}", addr);
+        }
+
+        let listeners = state.shutdown.listeners_token();
+        Some(tokio::spawn(async move {
+            axum::serve(listener, http_app)
+                .with_graceful_shutdown(listeners.cancelled_owned())
+                .await
+                .expect("Failed to start HTTP server");
+        }))
+    } else {
+        None
+    };
     
     // HTTPS server
-    axum_server::bind_rustls("127.0.0.1:3443".parse().unwrap(), config)
-        .serve(app.into_make_service())
-        .await
-        .expect("Failed to start HTTPS server");
+    let handle = Handle::new();
+    let https_server = if mode.serves_https() {
+        // Configure TLS, issuing a certificate first if there is none
+        if let Err(e) = tls::ensure_certificate(&state.config) {
+            panic!("Failed to provision SSL certificate: {}", e);
+        }
+        let (server_config, info) =
+            tls::load_server_config(&state.config, &state.acme)
+                .unwrap_or_else(|e| panic!("Failed to load SSL certificate: {}", e));
+        println!(
+            "🔐 Certificate: {} (expires {}, SHA-256 {})",
+            info.subject, info.not_after, info.fingerprint
+        );
+        *state.cert.write().unwrap() = Some(info);
+        let tls_config = RustlsConfig::from_config(Arc::new(server_config));
+
+        // Swap in renewed certificates without restarting
+        tokio::spawn(tls::watch_and_reload(
+            tls_config.clone(),
+            state.cert.clone(),
+            state.config.clone(),
+            state.acme.clone(),
+        ));
+        tokio::spawn(tls::renew_periodically(
+            tls_config.clone(),
+            state.cert.clone(),
+            state.config.clone(),
+            state.acme.clone(),
+        ));
+        if state.config.acme_enabled() {
+            tokio::spawn(acme::run(
+                tls_config.clone(),
+                state.cert.clone(),
+                state.config.clone(),
+                state.acme.clone(),
+            ));
+        }
+
+        let https_app = listeners::with_hsts(app, &state.config);
+        let addr = state.config.https_addr;
+        println!("🚀 HTTPS server started at https://{}", addr);
+        match state.config.client_auth {
+            ClientAuth::Off => {}
+            ClientAuth::Optional => println!("🪪 Client certificates verified when presented"),
+            ClientAuth::Required => println!("🪪 Client certificates required"),
+        }
+
+        // Tag requests with the client certificate identity
+        let acceptor = ClientCertAcceptor::new(
+            RustlsAcceptor::new(tls_config),
+            state.config.client_identity,
+        );
+        let https_handle = handle.clone();
+        Some(tokio::spawn(async move {
+            axum_server::bind(addr)
+                .acceptor(acceptor)
+                .handle(https_handle)
+                .serve(https_app.into_make_service())
+                .await
+                .expect("Failed to start HTTPS server");
+        }))
+    } else {
+        None
+    };
+
+    // Drain conversions on Ctrl-C / SIGTERM, then stop the listeners
+    shutdown::wait_for_signal().await;
+    println!("🛑 Shutdown requested, no longer accepting conversions");
+    state
+        .shutdown
+        .drain(Duration::from_secs(state.config.shutdown_timeout_secs))
+        .await;
+    if let Err(e) = state.tasks.save() {
+        eprintln!("⚠️  Failed to save task state: {}", e);
+    }
+    handle.graceful_shutdown(Some(LISTENER_GRACE_PERIOD));
+    for server in [http_server, https_server].into_iter().flatten() {
+        let _ = server.await;
+    }
+    println!("👋 Server stopped");
 }
 
 // Home page
@@ -138,7 +401,7 @@ async fn index_page() -> Html<&'static str> {
                 font-weight: 500;
             }
             
-            input[type="url"] {
+            input[type="url"], input[type="text"] {
                 width: 100%;
                 padding: 12px;
                 border: 2px solid #ddd;
@@ -147,11 +410,29 @@ async fn index_page() -> Html<&'static str> {
                 transition: border-color 0.3s;
             }
             
-            input[type="url"]:focus {
+            input[type="url"]:focus, input[type="text"]:focus {
                 outline: none;
                 border-color: #667eea;
             }
             
+            .checkbox-label {
+                display: flex;
+                align-items: center;
+                gap: 0.5rem;
+                font-weight: normal;
+                cursor: pointer;
+            }
+            
+            .track-list {
+                margin: 10px 0;
+                padding-left: 1.5rem;
+                font-size: 0.9rem;
+            }
+            
+            .track-list a {
+                color: #155724;
+            }
+            
             .convert-btn {
                 width: 100%;
                 padding: 12px;
@@ -215,6 +496,79 @@ async fn index_page() -> Html<&'static str> {
                 background-color: #218838;
             }
             
+            .batch-panel {
+                margin-top: 1.5rem;
+                color: #555;
+            }
+            
+            .batch-panel summary {
+                cursor: pointer;
+                font-weight: 500;
+                margin-bottom: 1rem;
+            }
+            
+            textarea {
+                width: 100%;
+                padding: 12px;
+                border: 2px solid #ddd;
+                border-radius: 8px;
+                font-size: 14px;
+                font-family: inherit;
+                resize: vertical;
+            }
+            
+            textarea:focus {
+                outline: none;
+                border-color: #667eea;
+            }
+            
+            #batchStatus {
+                margin-top: 1.5rem;
+            }
+            
+            .progress {
+                height: 10px;
+                background-color: #eee;
+                border-radius: 5px;
+                overflow: hidden;
+                margin: 0.5rem 0 1rem;
+            }
+            
+            .progress-bar {
+                height: 100%;
+                background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+                transition: width 0.3s;
+            }
+            
+            .batch-item {
+                padding: 0.5rem 0;
+                border-bottom: 1px solid #eee;
+                font-size: 0.9rem;
+                overflow-wrap: anywhere;
+            }
+            
+            .batch-item .download-link {
+                margin-top: 5px;
+                padding: 4px 10px;
+            }
+            
+            .batch-errors {
+                margin-bottom: 1rem;
+                padding: 0.75rem 1rem;
+                border-radius: 8px;
+                background-color: #f8d7da;
+                color: #721c24;
+                font-size: 0.9rem;
+            }
+            
+            .history-link {
+                display: block;
+                margin-top: 1.5rem;
+                text-align: center;
+                color: #667eea;
+                text-decoration: none;
+            }
+            
             .spinner {
                 border: 2px solid #f3f3f3;
                 border-top: 2px solid #667eea;
@@ -246,14 +600,111 @@ async fn index_page() -> Html<&'static str> {
                         required
                     >
                 </div>
+                <div class="form-group">
+                    <label class="checkbox-label">
+                        <input type="checkbox" name="split_chapters" value="true">
+                        Split by chapters (one track per chapter)
+                    </label>
+                    <label class="checkbox-label">
+                        <input type="checkbox" name="normalize" value="true">
+                        Normalize loudness (EBU R128)
+                    </label>
+                    <label class="checkbox-label">
+                        <input type="checkbox" name="square_thumbnail" value="true">
+                        Square cover art (crop the thumbnail)
+                    </label>
+                </div>
+                <div class="form-group">
+                    <label for="subtitles">Subtitle languages (optional):</label>
+                    <input type="text" id="subtitles" name="subtitles" placeholder="e.g. en, de">
+                    <label class="checkbox-label">
+                        <input type="checkbox" name="auto_subtitles" value="true">
+                        Use auto-generated captions when there are no subtitles
+                    </label>
+                    <label class="checkbox-label">
+                        <input type="checkbox" name="embed_lyrics" value="true">
+                        Embed subtitles as lyrics in the MP3
+                    </label>
+                </div>
+                <div class="form-group">
+                    <label for="collection">Podcast collection (optional):</label>
+                    <input type="text" id="collection" name="collection" maxlength="100" placeholder="e.g. Lectures">
+                </div>
+                <div class="form-group">
+                    <label for="proxy">Proxy (optional, instead of the server's):</label>
+                    <input type="text" id="proxy" name="proxy" autocomplete="off" placeholder="e.g. http://proxy.example:8080">
+                </div>
                 <button type="submit" class="convert-btn" id="convertBtn">
                     Start Conversion
                 </button>
             </form>
             
             <div id="status"></div>
+            
+            <details class="batch-panel">
+                <summary>📋 Convert several URLs</summary>
+                <form id="batchForm">
+                    <div class="form-group">
+                        <label for="batch_urls">YouTube URLs, one per line:</label>
+                        <textarea id="batch_urls" name="urls" rows="5" placeholder="https://www.youtube.com/watch?v=..."></textarea>
+                    </div>
+                    <div class="form-group">
+                        <label for="batch_file">Or upload a text/CSV file:</label>
+                        <input type="file" id="batch_file" name="file" accept=".txt,.csv,text/plain,text/csv">
+                    </div>
+                    <div class="form-group">
+                        <label class="checkbox-label">
+                            <input type="checkbox" name="split_chapters" value="true">
+                            Split by chapters
+                        </label>
+                        <label class="checkbox-label">
+                            <input type="checkbox" name="normalize" value="true">
+                            Normalize loudness
+                        </label>
+                        <label class="checkbox-label">
+                            <input type="checkbox" name="square_thumbnail" value="true">
+                            Square cover art
+                        </label>
+                    </div>
+                    <div class="form-group">
+                        <label for="batch_subtitles">Subtitle languages (optional):</label>
+                        <input type="text" id="batch_subtitles" name="subtitles" placeholder="e.g. en, de">
+                        <label class="checkbox-label">
+                            <input type="checkbox" name="auto_subtitles" value="true">
+                            Use auto-generated captions
+                        </label>
+                        <label class="checkbox-label">
+                            <input type="checkbox" name="embed_lyrics" value="true">
+                            Embed as lyrics
+                        </label>
+                    </div>
+                    <div class="form-group">
+                        <label for="batch_collection">Podcast collection (optional):</label>
+                        <input type="text" id="batch_collection" name="collection" maxlength="100">
+                    </div>
+                    <div class="form-group">
+                        <label for="batch_proxy">Proxy (optional):</label>
+                        <input type="text" id="batch_proxy" name="proxy" autocomplete="off">
+                    </div>
+                    <button type="submit" class="convert-btn" id="batchBtn">
+                        Start Batch
+                    </button>
+                </form>
+                <div id="batchStatus"></div>
+            </details>
+            <details class="batch-panel">
+                <summary>🍪 YouTube cookies</summary>
+                <p id="cookieStatus">Members-only and age-restricted videos need the cookies of a signed-in browser, exported as cookies.txt.</p>
+                <div class="form-group">
+                    <input type="file" id="cookieFile" accept=".txt,text/plain">
+                </div>
+                <button type="button" class="convert-btn" id="cookieUpload">Upload cookies.txt</button>
+                <button type="button" class="convert-btn" id="cookieDelete" style="display: none;">Remove my cookies</button>
+            </details>
+            <a href="/history" class="history-link">📜 Conversion history</a>
         </div>
         
+        <script src="/player.js"></script>
         <script>
             let currentTaskId = null;
             let statusInterval = null;
@@ -318,20 +769,60 @@ async fn index_page() -> Html<&'static str> {
                         if (result.thumbnail) {
                             thumbnailHtml = `
                                 <div style="margin: 10px 0;">
-                                    <img src="/thumbnail/${result.thumbnail}" 
+                                    <img src="/thumbnail/${encodeURIComponent(result.thumbnail)}?size=256" 
                                          alt="Video thumbnail" 
                                          style="max-width: 200px; max-height: 150px; border-radius: 8px; box-shadow: 0 2px 8px rgba(0,0,0,0.1);">
                                 </div>
                             `;
                         }
                         
-                        statusDiv.innerHTML = `
-                            ✅ Conversion completed!
-                            ${thumbnailHtml}
-                            <a href="/download/${result.filename}" class="download-link" download>
-                                📥 Download MP3
-                            </a>
-                        `;
+                        if (result.loudness) {
+                            const { input, output } = result.loudness;
+                            thumbnailHtml += `<div>🔊 Loudness ${input.integrated.toFixed(1)} → ${output.integrated.toFixed(1)} LUFS, peak ${output.true_peak.toFixed(1)} dBTP</div>`;
+                        }
+                        
+                        const sidecarLinks = (result.artifacts || [])
+                            .filter(artifact => artifact.kind === 'subtitles' || artifact.kind === 'lyrics')
+                            .map(artifact => `
+                                <a href="/download/${encodeURIComponent(artifact.filename)}" class="download-link" download>
+                                    ${artifact.kind === 'lyrics' ? '🎤' : '📝'} ${escapeHtml(artifact.language)} ${artifact.kind === 'lyrics' ? 'LRC' : 'SRT'}
+                                </a>
+                            `).join('');
+                        
+                        const tracks = (result.artifacts || []).filter(artifact => artifact.kind === 'track');
+                        const playlist = (result.artifacts || []).find(artifact => artifact.kind === 'playlist');
+                        if (tracks.length > 0) {
+                            const trackItems = tracks.map(track => `
+                                <li><a href="/download/${encodeURIComponent(track.filename)}" download>${escapeHtml(track.title || track.filename)}</a></li>
+                            `).join('');
+                            statusDiv.innerHTML = `
+                                ✅ Conversion completed, ${tracks.length} chapters!
+                                ${thumbnailHtml}
+                                <ol class="track-list">${trackItems}</ol>
+                                <a href="/download/${encodeURIComponent(result.filename)}" class="download-link" download>
+                                    📦 Download all (ZIP)
+                                </a>
+                                ${playlist ? `<a href="/download/${encodeURIComponent(playlist.filename)}" class="download-link" download>🎶 M3U playlist</a>` : ''}
+                                ${sidecarLinks}
+                                <div class="player-slot"></div>
+                            `;
+                        } else {
+                            statusDiv.innerHTML = `
+                                ✅ Conversion completed!
+                                ${thumbnailHtml}
+                                <a href="/download/${result.filename}" class="download-link" download>
+                                    📥 Download MP3
+                                </a>
+                                ${sidecarLinks}
+                                <div class="player-slot"></div>
+                            `;
+                        }
+                        
+                        // Preview the tracks, or the single MP3, before downloading
+                        const previews = tracks.length > 0
+                            ? tracks.map(track => ({ filename: track.filename, title: track.title }))
+                            : [{ filename: result.filename, title: result.title }];
+                        createPlayer(statusDiv.querySelector('.player-slot'), previews);
                         
                         convertBtn.disabled = false;
                         convertBtn.innerHTML = 'Start Conversion';
@@ -349,6 +840,119 @@ async fn index_page() -> Html<&'static str> {
                     console.error('Error occurred while checking status:', error);
                 }
             }
+            
+            let batchInterval = null;
+            
+            function escapeHtml(text) {
+                const div = document.createElement('div');
+                div.textContent = text;
+                return div.innerHTML;
+            }
+            
+            // The cookies themselves never come back from the server, only a summary
+            async function loadCookieStatus() {
+                const status = document.getElementById('cookieStatus');
+                const result = await fetch('/api/v1/cookies').then(r => r.json()).catch(() => null);
+                if (!result) return;
+                if (result.uploaded) {
+                    status.textContent = `✅ ${result.cookies} cookies uploaded for ${result.domains.join(', ')}`;
+                } else if (result.global) {
+                    status.textContent = 'ℹ️ The server\'s cookies are used. Upload your own cookies.txt to use your account instead.';
+                }
+                document.getElementById('cookieDelete').style.display = result.uploaded ? '' : 'none';
+            }
+            loadCookieStatus();
+            
+            document.getElementById('cookieUpload').addEventListener('click', async () => {
+                const file = document.getElementById('cookieFile').files[0];
+                if (!file) return;
+                const response = await fetch('/api/v1/cookies', { method: 'PUT', body: file });
+                const result = await response.json().catch(() => ({}));
+                if (!response.ok) {
+                    alert(`❌ ${result.error || 'Upload failed'}`);
+                }
+                document.getElementById('cookieFile').value = '';
+                loadCookieStatus();
+            });
+            
+            document.getElementById('cookieDelete').addEventListener('click', async () => {
+                if (!confirm('Remove your uploaded cookies?')) return;
+                await fetch('/api/v1/cookies', { method: 'DELETE' });
+                document.getElementById('cookieStatus').textContent = 'Cookies removed.';
+                loadCookieStatus();
+            });
+            
+            document.getElementById('batchForm').addEventListener('submit', async (e) => {
+                e.preventDefault();
+                
+                const batchBtn = document.getElementById('batchBtn');
+                const batchStatus = document.getElementById('batchStatus');
+                batchBtn.disabled = true;
+                batchBtn.innerHTML = '<div class="spinner"></div>Submitting...';
+                clearInterval(batchInterval);
+                
+                try {
+                    const response = await fetch('/batches', {
+                        method: 'POST',
+                        body: new FormData(e.target)
+                    });
+                    const result = await response.json();
+                    const errors = (result.errors || [])
+                        .map(item => `<li>Item ${item.index + 1}: ${escapeHtml(item.input)} (${escapeHtml(item.error)})</li>`)
+                        .join('');
+                    const errorsHtml = errors ? `<div class="batch-errors">⚠️ Skipped:<ul>${errors}</ul></div>` : '';
+                    
+                    if (!response.ok) {
+                        batchStatus.innerHTML = `${errorsHtml}<div class="batch-errors">❌ ${escapeHtml(result.error || 'Batch submission failed')}</div>`;
+                        return;
+                    }
+                    
+                    batchStatus.innerHTML = `${errorsHtml}<div id="batchProgress"></div>`;
+                    const update = () => checkBatch(result.batch_id);
+                    update();
+                    batchInterval = setInterval(update, 2000);
+                } catch (error) {
+                    batchStatus.innerHTML = `<div class="batch-errors">❌ Error: ${escapeHtml(error.message)}</div>`;
+                } finally {
+                    batchBtn.disabled = false;
+                    batchBtn.innerHTML = 'Start Batch';
+                }
+            });
+            
+            async function checkBatch(batchId) {
+                try {
+                    const response = await fetch(`/batches/${batchId}`);
+                    const result = await response.json();
+                    if (!response.ok) {
+                        clearInterval(batchInterval);
+                        return;
+                    }
+                    
+                    const items = result.tasks.map(task => {
+                        let state = '⏳ Converting...';
+                        if (task.status === 'completed') {
+                            const label = task.filename.endsWith('.zip') ? `📦 ${escapeHtml(task.title || 'Download')} (ZIP)` : `📥 ${escapeHtml(task.title || 'Download MP3')}`;
+                            state = `✅ <a href="/download/${encodeURIComponent(task.filename)}" class="download-link" download>${label}</a>`;
+                        } else if (task.status === 'failed') {
+                            state = `❌ ${escapeHtml(task.error)}`;
+                        }
+                        return `<div class="batch-item">${escapeHtml(task.url || '')}<br>${state}</div>`;
+                    }).join('');
+                    
+                    const done = result.completed + result.failed;
+                    document.getElementById('batchProgress').innerHTML = `
+                        <div>${done} of ${result.total} done, ${result.completed} completed, ${result.failed} failed</div>
+                        <div class="progress"><div class="progress-bar" style="width: ${result.progress}%"></div></div>
+                        ${items}
+                    `;
+                    
+                    if (result.status !== 'processing') {
+                        clearInterval(batchInterval);
+                    }
+                } catch (error) {
+                    console.error('Error occurred while checking batch:', error);
+                }
+            }
         </script>
     </body>
     </html>
@@ -357,174 +961,591 @@ async fn index_page() -> Html<&'static str> {
 
 // Conversion processing
 async fn convert_youtube(
-    axum::extract::State(tasks): axum::extract::State<TaskMap>,
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    base_url: Option<BaseUrl>,
     Form(form): Form<ConvertForm>,
-) -> Result<axum::response::Json<serde_json::Value>, StatusCode> {
+) -> Result<axum::response::Json<serde_json::Value>, (StatusCode, axum::response::Json<serde_json::Value>)> {
+    // Refuse new work while draining for shutdown
+    if state.shutdown.is_draining() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            axum::response::Json(serde_json::json!({
+                "error": "Server is shutting down, please try again later"
+            })),
+        ));
+    }
+
     // Validate YouTube URL
-    if !form.youtube_url.contains("youtube.com") && !form.youtube_url.contains("youtu.be") {
-        return Ok(axum::response::Json(serde_json::json!({
-            "error": "Please provide a valid YouTube URL"
-        })));
+    if let Err(error) = validate_youtube_url(&form.youtube_url) {
+        return Ok(axum::response::Json(serde_json::json!({ "error": error })));
     }
+
+    let callback_url = match parse_callback_url(form.callback_url, &state.config).await {
+        Ok(callback_url) => callback_url,
+        Err(error) => return Ok(axum::response::Json(serde_json::json!({ "error": error }))),
+    };
+
+    let collection = match parse_collection(form.collection) {
+        Ok(collection) => collection,
+        Err(error) => return Ok(axum::response::Json(serde_json::json!({ "error": error }))),
+    };
+
+    let subtitles = match subtitles::parse_languages(form.subtitles) {
+        Ok(subtitles) => subtitles,
+        Err(error) => return Ok(axum::response::Json(serde_json::json!({ "error": error }))),
+    };
+
+    let proxy = match credentials::parse_proxy(form.proxy) {
+        Ok(proxy) => proxy,
+        Err(error) => return Ok(axum::response::Json(serde_json::json!({ "error": error }))),
+    };
+
+    // Mark task as processing, owned by the submitting client
+    let record = TaskRecord {
+        options: ConvertOptions {
+            split_chapters: form.split_chapters,
+            normalize: form.normalize,
+            square_thumbnail: form.square_thumbnail,
+            subtitles,
+            auto_subtitles: form.auto_subtitles,
+            embed_lyrics: form.embed_lyrics,
+            proxy,
+            format: None,
+        },
+        collection,
+        base_url: base_url.map(|BaseUrl(base)| base),
+        ..TaskRecord::new(Some(form.youtube_url), identity.0, callback_url)
+    };
+    let task_id = start_conversion(&state, record);
     
+    Ok(axum::response::Json(serde_json::json!({
+        "task_id": task_id,
+        "status": "processing"
+    })))
+}
+
+// Only YouTube video and playlist URLs are converted
+fn validate_youtube_url(url: &str) -> Result<(), String> {
+    if url.contains("youtube.com") || url.contains("youtu.be") {
+        Ok(())
+    } else {
+        Err("Please provide a valid YouTube URL".to_string())
+    }
+}
+
+// Only absolute http(s) URLs outside the server's networks can receive
+// callbacks; blank means none
+async fn parse_callback_url(
+    callback_url: Option<String>,
+    config: &Config,
+) -> Result<Option<String>, String> {
+    let callback_url = callback_url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    if let Some(url) = &callback_url {
+        let valid = reqwest::Url::parse(url)
+            .map(|parsed| matches!(parsed.scheme(), "http" | "https"))
+            .unwrap_or(false);
+        if !valid {
+            return Err("callback_url must be an http or https URL".to_string());
+        }
+        webhooks::check_callback_url(url, config).await?;
+    }
+    Ok(callback_url)
+}
+
+// Collection names are free text; blank means none
+fn parse_collection(collection: Option<String>) -> Result<Option<String>, String> {
+    let collection = collection
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    match collection {
+        Some(name) if name.chars().count() > 100 => {
+            Err("collection must be at most 100 characters".to_string())
+        }
+        collection => Ok(collection),
+    }
+}
+
+// Register the task and convert its URL in the background, returning the task ID
+fn start_conversion(state: &AppState, mut record: TaskRecord) -> String {
     // Generate unique task ID
     let task_id = Uuid::new_v4().to_string();
-    
-    // Mark task as processing
-    {
-        let mut tasks_lock = tasks.lock().unwrap();
-        tasks_lock.insert(task_id.clone(), TaskStatus::Processing);
-    }
-    
+    record.options.normalize |= state.config.normalize;
+    record.options.square_thumbnail |= state.config.thumbnail_square;
+    state.tasks.create(&task_id, record);
+    run_conversion(state, &task_id);
+    task_id
+}
+
+// Convert a registered task's URL in the background
+//
+// Retryable failures are tried again after a backoff, up to
+// `retry_max_attempts` runs; the worker is released while waiting.
+fn run_conversion(state: &AppState, task_id: &str) {
+    let Some(record) = state.tasks.get(task_id) else {
+        return;
+    };
+    let url = record.url.unwrap_or_default();
+    let options = record.options;
+    let owner = record.owner;
+
     // Execute conversion asynchronously
-    let tasks_clone = tasks.clone();
-    let task_id_clone = task_id.clone();
-    let url = form.youtube_url.clone();
+    let task_id = task_id.to_string();
+    let state = state.clone();
+    let abort = state.shutdown.abort_token();
+    let draining = state.shutdown.draining_token();
+    let shutdown = state.shutdown.clone();
     
-    tokio::spawn(async move {
-        let result = perform_conversion(&url).await;
-        
-        let mut tasks_lock = tasks_clone.lock().unwrap();
-        match result {
-            Ok((audio_filename, thumbnail_filename)) => {
-                tasks_lock.insert(task_id_clone, TaskStatus::Completed(audio_filename, thumbnail_filename));
+    shutdown.spawn_conversion(async move {
+        let max_attempts = state.config.retry_max_attempts;
+        for attempt in 1..=max_attempts {
+            let result = {
+                // Wait for a free conversion worker; queued tasks never start once draining
+                let _permit = tokio::select! {
+                    permit = state.queue.acquire() => permit,
+                    _ = abort.cancelled() => {
+                        fail_task(&state, &task_id, Failure::new(FailureKind::Cancelled));
+                        return;
+                    }
+                };
+                if state.shutdown.is_draining() {
+                    fail_task(&state, &task_id, Failure::new(FailureKind::Cancelled));
+                    return;
+                }
+                state.tasks.start_attempt(&task_id);
+                attempt_conversion(&url, &options, &state.config, owner.as_deref(), std::path::Path::new(DOWNLOAD_DIR), &abort, &log_progress).await
+            };
+
+            let failure = match result {
+                Ok(conversion) => {
+                    state.tasks.end_attempt(&task_id, None);
+                    state.tasks.update(&task_id, |record| {
+                        record.artifacts = conversion.artifacts;
+                        record.loudness = conversion.loudness;
+                        record.duration = conversion.duration;
+                    });
+                    finish_task(&state, &task_id, TaskStatus::Completed(conversion.audio, conversion.thumbnail));
+                    return;
+                }
+                Err(failure) => failure,
+            };
+            if let Some(detail) = &failure.detail {
+                eprintln!("⚠️  Task {} run {} failed ({:?}): {}", task_id, attempt, failure.kind, detail);
             }
-            Err(error) => {
-                tasks_lock.insert(task_id_clone, TaskStatus::Failed(error));
+            state.tasks.end_attempt(&task_id, Some(&failure));
+            if !failure.kind.retryable() || attempt == max_attempts {
+                fail_task(&state, &task_id, failure);
+                return;
+            }
+
+            let config = &state.config;
+            let delay = failures::backoff(config.retry_base_delay_secs, config.retry_max_delay_secs, attempt);
+            println!(
+                "🔄 Retrying {} in {:.1}s (attempt {} of {}): {}",
+                url,
+                delay.as_secs_f64(),
+                attempt + 1,
+                max_attempts,
+                failure.message
+            );
+            // A retry waiting for its turn gives up as soon as shutdown starts
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = draining.cancelled() => {
+                    fail_task(&state, &task_id, Failure::new(FailureKind::Cancelled));
+                    return;
+                }
             }
         }
     });
-    
-    Ok(axum::response::Json(serde_json::json!({
-        "task_id": task_id,
-        "status": "processing"
-    })))
 }
 
-// Actually perform conversion
-async fn perform_conversion(url: &str) -> Result<(String, Option<String>), String> {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+// One run of a conversion into `dir`, stopped once it takes longer than the task timeout
+async fn attempt_conversion(
+    url: &str,
+    options: &ConvertOptions,
+    config: &Config,
+    owner: Option<&str>,
+    dir: &std::path::Path,
+    abort: &CancellationToken,
+    progress: &(dyn Fn(Progress) + Sync),
+) -> Result<Conversion, Failure> {
+    // Timing out cancels the run like a shutdown would, cleaning up after it
+    let run = abort.child_token();
+    let timeout = config.task_timeout_secs;
+    let timer = (timeout > 0).then(|| {
+        let run = run.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(timeout)).await;
+            run.cancel();
+        })
+    });
+    // Read for every run, so a newly uploaded cookie jar is used by retries;
+    // the proxy password is masked in the output kept with a failure
+    let result = match credentials::Access::for_task(config, owner, options.proxy.as_deref()) {
+        Ok(access) => perform_conversion(url, options, config, &access, dir, &run, progress)
+            .await
+            .map_err(|failure| Failure {
+                detail: failure.detail.as_deref().map(|detail| access.redact(detail)),
+                ..failure
+            }),
+        Err(e) => Err(Failure::from(e)),
+    };
+    if let Some(timer) = timer {
+        timer.abort();
+    }
+    match result {
+        Err(_) if run.is_cancelled() && !abort.is_cancelled() => {
+            eprintln!("⚠️  {} timed out after {}s", url, timeout);
+            Err(Failure {
+                message: format!(
+                    "The conversion took longer than the limit of {}.",
+                    feeds::duration(timeout as f64)
+                ),
+                ..Failure::new(FailureKind::Timeout)
+            })
+        }
+        result => result,
+    }
+}
+
+// POST /tasks/:task_id/retry, convert a failed task again
+async fn retry_task(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    base_url: Option<BaseUrl>,
+    Path(task_id): Path<String>,
+) -> Result<(StatusCode, axum::response::Json<serde_json::Value>), (StatusCode, axum::response::Json<serde_json::Value>)> {
+    let record = state
+        .tasks
+        .get(&task_id)
+        .filter(|record| identity.may_access(record.owner.as_deref()))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                axum::response::Json(serde_json::json!({
+                    "status": "not_found",
+                    "error": "Task not found"
+                })),
+            )
+        })?;
+    if state.shutdown.is_draining() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            axum::response::Json(serde_json::json!({
+                "error": "Server is shutting down, please try again later"
+            })),
+        ));
+    }
+    if record.url.is_some() && matches!(record.status, TaskStatus::Completed(..)) {
+        // A completed task keeps its files; the new conversion gets its own task
+        let copy = TaskRecord {
+            options: record.options,
+            collection: record.collection,
+            base_url: base_url.map(|BaseUrl(base)| base).or(record.base_url),
+            ..TaskRecord::new(record.url, identity.0, record.callback_url)
+        };
+        let task_id = start_conversion(&state, copy);
+        return Ok((
+            StatusCode::ACCEPTED,
+            axum::response::Json(serde_json::json!({
+                "task_id": task_id,
+                "status": "processing"
+            })),
+        ));
+    }
+    if record.url.is_none() || !state.tasks.reopen(&task_id) {
+        return Err((
+            StatusCode::CONFLICT,
+            axum::response::Json(serde_json::json!({
+                "error": "Only finished tasks can be retried"
+            })),
+        ));
+    }
+
+    run_conversion(&state, &task_id);
+    Ok((
+        StatusCode::ACCEPTED,
+        axum::response::Json(serde_json::json!({
+            "task_id": task_id,
+            "status": "processing"
+        })),
+    ))
+}
+
+// Record a task's final state and notify its webhooks
+fn finish_task(state: &AppState, task_id: &str, status: TaskStatus) {
+    state.tasks.set(task_id, status);
+    state.webhooks.notify(task_id);
+}
+
+// Record why a task failed, then finish it
+fn fail_task(state: &AppState, task_id: &str, failure: Failure) {
+    state.tasks.update(task_id, |record| record.error_code = Some(failure.kind));
+    finish_task(state, task_id, TaskStatus::Failed(failure.message));
+}
+
+// Actually perform conversion, writing the files to `dir`
+//
+// yt-dlp is killed and its partial output removed if `abort` fires first.
+// The audio is normalized before it is split, so every chapter gets the same gain.
+async fn perform_conversion(
+    url: &str,
+    options: &ConvertOptions,
+    config: &Config,
+    access: &credentials::Access,
+    dir: &std::path::Path,
+    abort: &CancellationToken,
+    progress: &(dyn Fn(Progress) + Sync),
+) -> Result<Conversion, Failure> {
+    let timestamp = conversion_timestamp();
     
-    // Use timestamp to create unique output template, avoiding filename conflicts
-    let output_template = format!("downloads/%(title).100s_{}.%(ext)s", timestamp);
+    // Refuse videos over the limits before downloading anything
+    if limits::enabled(config) {
+        progress(Progress::Step("Checking video"));
+        limits::preflight(config, url, options.format.as_deref(), access, abort).await?;
+    }
+
+    // The timestamp marks this conversion's files, keeping them apart from others
+    let output_template = dir.join(format!("%(title).100s_{}.%(ext)s", timestamp));
     
     // Execute yt-dlp command to download audio and thumbnail
-    let output = Command::new("bin/yt-dlp.exe")
-        .args([
-            "--extract-audio",
-            "--audio-format", "mp3",
-            "--audio-quality", "192K",
-            "--write-thumbnail",
-            "--output", &output_template,
-            url,
-        ])
-        .output();
+    let mut command = Command::new(&config.yt_dlp_path);
+    command.args([
+        "--extract-audio",
+        "--audio-format", "mp3",
+        "--audio-quality", "192K",
+        "--write-thumbnail",
+    ]);
+    command.arg("--output").arg(&output_template);
+    // yt-dlp extracts the audio with the same FFmpeg as the later steps
+    command.arg("--ffmpeg-location").arg(&config.ffmpeg_path);
+    if let Some(format) = &options.format {
+        command.args(["--format", format]);
+    }
+    if !options.subtitles.is_empty() {
+        command.args(subtitles::yt_dlp_args(&options.subtitles, options.auto_subtitles));
+    }
+    command.args(limits::yt_dlp_args(Limits::of(config)));
+    command.args(access.args());
+    // Duration and chapter markers are read from the info JSON;
+    // progress is printed one line per update. A video URL within a playlist
+    // converts only the video, as the limits pre-flight checked.
+    let child = command
+        .args(["--no-playlist", "--newline", "--write-info-json", url])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    
+    progress(Progress::Step("Downloading"));
+    let output = match child {
+        Ok(mut child) => {
+            // The output is read while yt-dlp runs to report the download progress
+            let stdout = child.stdout.take().expect("stdout is piped");
+            let read_stdout = async {
+                let mut lines = tokio::io::BufReader::new(stdout).lines();
+                let mut text = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Some(percent) = download_percent(&line) {
+                        progress(Progress::Downloaded(percent));
+                    }
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+                text
+            };
+            tokio::select! {
+                (stdout, output) = async { tokio::join!(read_stdout, child.wait_with_output()) } => {
+                    output.map(|output| (output, stdout))
+                }
+                _ = abort.cancelled() => {
+                    // Dropping the child kills yt-dlp
+                    remove_partial_files(dir, timestamp);
+                    return Err(Failure::new(FailureKind::Cancelled));
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
     
     match output {
-        Ok(result) => {
+        Ok((result, stdout)) => {
             if result.status.success() {
-                // Find the latest created mp3 and thumbnail files
-                match find_latest_downloaded_files(timestamp) {
-                    Some((audio_filename, thumbnail_filename)) => {
-                        Ok((audio_filename, thumbnail_filename))
+                // Videos over the limits are skipped by yt-dlp without an error
+                if let Some(failure) = limits::rejection(&stdout, Limits::of(config)) {
+                    remove_partial_files(dir, timestamp);
+                    return Err(failure);
+                }
+                let (audio_filename, thumbnail_filename) = find_downloaded_files(dir, timestamp)
+                    .ok_or_else(|| "Conversion completed but downloaded files not found".to_string())?;
+                if let Err(failure) = limits::check_output(Limits::of(config), dir, &audio_filename) {
+                    remove_partial_files(dir, timestamp);
+                    return Err(failure);
+                }
+                let info = VideoInfo::take(dir, &audio_filename).unwrap_or_else(|e| {
+                    eprintln!("⚠️  {}", e);
+                    VideoInfo::default()
+                });
+                let mut conversion = Conversion {
+                    audio: audio_filename,
+                    thumbnail: thumbnail_filename,
+                    artifacts: Vec::new(),
+                    loudness: None,
+                    duration: info.duration,
+                };
+                // A thumbnail that cannot be converted is kept as downloaded
+                if let Some(thumbnail) = conversion.thumbnail.clone() {
+                    progress(Progress::Step("Converting thumbnail"));
+                    let (format, square) = (config.thumbnail_format, options.square_thumbnail);
+                    let thumbnail_dir = dir.to_path_buf();
+                    match tokio::task::spawn_blocking(move || {
+                        thumbnails::normalize(&thumbnail_dir, &thumbnail, format, square)
+                    })
+                    .await
+                    {
+                        Ok(Ok(thumbnail)) => conversion.thumbnail = Some(thumbnail),
+                        Ok(Err(e)) => eprintln!("⚠️  {}", e),
+                        Err(e) => eprintln!("⚠️  Thumbnail conversion failed: {}", e),
                     }
-                    None => {
-                        Err("Conversion completed but downloaded files not found".to_string())
+                }
+                if options.normalize {
+                    progress(Progress::Step("Normalizing loudness"));
+                    conversion.loudness = loudness::normalize(config, dir, &conversion.audio, abort).await?;
+                    if conversion.loudness.is_none() {
+                        progress(Progress::Note(format!("{} is silent, loudness left unchanged", url)));
                     }
                 }
+                // Sidecars and lyrics follow the full video, tracks get the part of their chapter
+                let mut lyrics = Vec::new();
+                if !options.subtitles.is_empty() {
+                    let (subtitles, sidecars) =
+                        subtitles::sidecars(dir, &conversion.audio, info.title.as_deref())?;
+                    if subtitles.is_empty() {
+                        progress(Progress::Note(format!("No subtitles found for {}", url)));
+                    }
+                    conversion.artifacts = sidecars;
+                    if options.embed_lyrics {
+                        subtitles::embed(
+                            dir.join(&conversion.audio),
+                            &subtitles,
+                            None,
+                        )
+                        .await?;
+                        lyrics = subtitles;
+                    }
+                }
+                if options.split_chapters {
+                    progress(Progress::Step("Splitting chapters"));
+                    match chapters::split(config, dir, &conversion.audio, info, &lyrics, abort).await? {
+                        Some(split) => {
+                            conversion.audio = split.archive;
+                            conversion.artifacts.extend(split.artifacts);
+                        }
+                        None => progress(Progress::Note(format!("No chapters in {}, kept as a single track", url))),
+                    }
+                }
+                // Waveforms for the player; the audio is still usable without one
+                let mut playable: Vec<String> = conversion
+                    .artifacts
+                    .iter()
+                    .filter(|artifact| artifact.kind == ArtifactKind::Track)
+                    .map(|artifact| artifact.filename.clone())
+                    .collect();
+                if playable.is_empty() {
+                    playable.push(conversion.audio.clone());
+                }
+                progress(Progress::Step("Computing waveform"));
+                for audio in playable {
+                    match waveform::generate(config, dir, &audio, abort).await {
+                        Ok(artifact) => conversion.artifacts.push(artifact),
+                        Err(failure) if failure.kind == FailureKind::Cancelled => return Err(failure),
+                        Err(_) => eprintln!("⚠️  Cannot compute waveform of {}, continuing without one", audio),
+                    }
+                }
+                Ok(conversion)
             } else {
+                // Do not leave the info JSON or partial downloads behind
+                remove_partial_files(dir, timestamp);
+                // The raw output is only logged, users get the classified failure
                 let error_msg = String::from_utf8_lossy(&result.stderr);
-                Err(format!("yt-dlp execution failed: {}", error_msg))
+                Err(Failure::from_yt_dlp(&error_msg))
             }
         }
-        Err(e) => Err(format!("Cannot execute bin/yt-dlp.exe: {}. Please ensure yt-dlp.exe is in the bin/ directory", e)),
+        Err(e) => {
+            eprintln!("⚠️  Cannot execute {}: {}. Please install yt-dlp in the bin/ directory or on the PATH, or set YT_MP3_YT_DLP", config.yt_dlp_path.display(), e);
+            Err(Failure::new(FailureKind::YtDlpMissing))
+        }
     }
 }
 
-// Find the latest downloaded files
-fn find_latest_downloaded_files(since_timestamp: u64) -> Option<(String, Option<String>)> {
-    use std::fs;
-    use std::time::UNIX_EPOCH;
-    
-    let downloads_dir = std::path::Path::new("downloads");
-    if !downloads_dir.exists() {
-        return None;
-    }
-    
-    let mut latest_audio_file = None;
-    let mut latest_audio_time = 0u64;
-    let mut latest_thumbnail_file = None;
-    let mut latest_thumbnail_time = 0u64;
-    
-    if let Ok(entries) = fs::read_dir(downloads_dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if let Ok(metadata) = entry.metadata() {
-                    if let Ok(modified) = metadata.modified() {
-                        if let Ok(duration) = modified.duration_since(UNIX_EPOCH) {
-                            let file_timestamp = duration.as_secs();
-                            // Check files created or modified after conversion started
-                            if file_timestamp >= since_timestamp {
-                                if let Some(filename) = path.file_name() {
-                                    let filename_str = filename.to_string_lossy().to_string();
-                                    
-                                    // Find the latest audio file
-                                    if filename_str.ends_with(".mp3") && file_timestamp > latest_audio_time {
-                                        latest_audio_file = Some(filename_str.clone());
-                                        latest_audio_time = file_timestamp;
-                                    } 
-                                    // Find the latest thumbnail file
-                                    else if (filename_str.ends_with(".jpg") || 
-                                              filename_str.ends_with(".jpeg") || 
-                                              filename_str.ends_with(".png") || 
-                                              filename_str.ends_with(".webp")) && 
-                                              file_timestamp > latest_thumbnail_time {
-                                        latest_thumbnail_file = Some(filename_str.clone());
-                                        latest_thumbnail_time = file_timestamp;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+// Percentage of a `[download]  42.0% of 3.50MiB at …` progress line
+fn download_percent(line: &str) -> Option<f64> {
+    line.strip_prefix("[download]")?
+        .split_whitespace()
+        .next()?
+        .strip_suffix('%')?
+        .parse()
+        .ok()
+}
+
+// Remove files left behind by an interrupted conversion
+fn remove_partial_files(dir: &std::path::Path, timestamp: u64) {
+    let marker = format!("_{}.", timestamp);
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().contains(&marker) {
+                let _ = fs::remove_file(entry.path());
             }
         }
     }
-    
-    // If no new audio file is found, try to find any mp3 file in downloads directory
-    // This handles cases where files are overwritten but timestamps are not updated
-    if latest_audio_file.is_none() {
-        if let Ok(entries) = fs::read_dir(downloads_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if let Some(filename) = path.file_name() {
-                        let filename_str = filename.to_string_lossy().to_string();
-                        if filename_str.ends_with(".mp3") {
-                            // Verify the file actually exists and is readable
-                            if path.exists() && fs::metadata(&path).is_ok() {
-                                latest_audio_file = Some(filename_str);
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
+}
+
+// Audio and thumbnail yt-dlp wrote for the conversion with `timestamp`
+//
+// Only names ending in the conversion's `_{timestamp}` marker count, so files of
+// other conversions running at the same time are never picked up.
+fn find_downloaded_files(dir: &std::path::Path, timestamp: u64) -> Option<(String, Option<String>)> {
+    let marker = format!("_{}", timestamp);
+    let mut audio = None;
+    let mut thumbnail = None;
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let Some((stem, extension)) = filename.rsplit_once('.') else {
+            continue;
+        };
+        if !stem.ends_with(&marker) {
+            continue;
+        }
+        match extension {
+            "mp3" => audio = Some(filename),
+            "jpg" | "jpeg" | "png" | "webp" => thumbnail = Some(filename),
+            _ => {}
         }
     }
-    
-    if let Some(audio) = latest_audio_file {
-        Some((audio, latest_thumbnail_file))
-    } else {
-        None
-    }
+    audio.map(|audio| (audio, thumbnail))
+}
+
+// Start second of a new conversion, later than that of any conversion before
+//
+// The second is the marker in the names of the conversion's files; no two
+// conversions of this process share one, even when started together.
+fn conversion_timestamp() -> u64 {
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let last = LAST
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(now.max(last + 1)))
+        .unwrap();
+    now.max(last + 1)
 }
 
 // Extract title from yt-dlp's JSON output
+#[allow(dead_code)]
 fn extract_title_from_json(json_str: &str) -> Option<String> {
     // Simple JSON parsing to get title field
     if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(json_str) {
@@ -551,8 +1572,8 @@ fn sanitize_filename(title: &str) -> String {
     sanitized = sanitized.trim().replace(' ', "_");
     
     // Limit length to avoid filename being too long
-    if sanitized.len() > 100 {
-        sanitized.truncate(100);
+    if sanitized.chars().count() > 100 {
+        sanitized = sanitized.chars().take(100).collect();
     }
     
     // If empty after cleaning, use default name
@@ -568,11 +1589,12 @@ fn sanitize_filename(title: &str) -> String {
 }
 
 // Find thumbnail file
+#[allow(dead_code)]
 fn find_thumbnail_file(base_filename: &str) -> Option<String> {
     let extensions = ["jpg", "jpeg", "png", "webp"];
     for ext in &extensions {
         let thumbnail_filename = format!("{}.{}", base_filename, ext);
-        let thumbnail_path = format!("downloads/{}", thumbnail_filename);
+        let thumbnail_path = format!("{}/{}", DOWNLOAD_DIR, thumbnail_filename);
         if std::path::Path::new(&thumbnail_path).exists() {
             return Some(thumbnail_filename);
         }
@@ -582,12 +1604,21 @@ fn find_thumbnail_file(base_filename: &str) -> Option<String> {
 
 // Check task status
 async fn check_status(
-    axum::extract::State(tasks): axum::extract::State<TaskMap>,
+    State(state): State<AppState>,
+    identity: ClientIdentity,
     Path(task_id): Path<String>,
 ) -> axum::response::Json<serde_json::Value> {
-    let tasks_lock = tasks.lock().unwrap();
-    
-    match tasks_lock.get(&task_id) {
+    // Other users' tasks are reported as missing
+    let record = state
+        .tasks
+        .get(&task_id)
+        .filter(|record| identity.may_access(record.owner.as_deref()));
+    let artifacts = record.as_ref().map(|record| record.artifacts.clone()).unwrap_or_default();
+    let loudness = record.as_ref().and_then(|record| record.loudness.clone());
+    let error_code = record.as_ref().and_then(|record| record.error_code);
+    let attempts = record.as_ref().map_or(0, |record| record.attempts.len());
+    let status = record.map(|record| record.status);
+    match status {
         Some(TaskStatus::Processing) => {
             axum::response::Json(serde_json::json!({
                 "status": "processing"
@@ -600,16 +1631,28 @@ async fn check_status(
             });
             
             if let Some(thumb_file) = thumbnail_filename {
-                response["thumbnail"] = serde_json::Value::String(thumb_file.clone());
+                response["thumbnail"] = serde_json::Value::String(thumb_file);
+            }
+            if !artifacts.is_empty() {
+                response["artifacts"] = serde_json::json!(artifacts);
+            }
+            if let Some(loudness) = loudness {
+                response["loudness"] = serde_json::json!(loudness);
             }
             
             axum::response::Json(response)
         }
         Some(TaskStatus::Failed(error)) => {
-            axum::response::Json(serde_json::json!({
+            let mut response = serde_json::json!({
                 "status": "failed",
                 "error": error
-            }))
+            });
+            if let Some(code) = error_code {
+                response["error_code"] = serde_json::json!(code);
+                response["retryable"] = serde_json::json!(code.retryable());
+            }
+            response["attempts"] = serde_json::json!(attempts);
+            axum::response::Json(response)
         }
         None => {
             axum::response::Json(serde_json::json!({
@@ -621,18 +1664,35 @@ async fn check_status(
 }
 
 // File download
-async fn download_file(Path(filename): Path<String>) -> impl IntoResponse {
-    let file_path = PathBuf::from("downloads").join(&filename);
+async fn download_file(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(filename): Path<String>,
+) -> impl IntoResponse {
+    let file_path = PathBuf::from(DOWNLOAD_DIR).join(&filename);
     
-    // Security check: ensure file is within downloads directory
-    if !file_path.starts_with("downloads") {
-        return Err(StatusCode::FORBIDDEN);
+    // Only files produced by a task are served, never other files of the directory
+    if !tasks::is_plain_filename(&filename) || !state.tasks.file_in_use(&filename) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if !identity.may_access(state.tasks.owner_of_file(&filename).as_deref()) {
+        return Err(StatusCode::NOT_FOUND);
     }
     
     match fs::read(&file_path) {
         Ok(contents) => {
+            // Chapter splits also produce a zip archive and an M3U playlist,
+            // subtitles an SRT and an LRC sidecar
+            let content_type = match file_path.extension().and_then(|ext| ext.to_str()) {
+                Some("zip") => "application/zip",
+                Some("m3u") => "audio/x-mpegurl",
+                Some("srt") => "application/x-subrip",
+                Some("lrc") => "text/plain; charset=utf-8",
+                Some("json") => "application/json",
+                _ => "audio/mpeg",
+            };
             let headers = [
-                (header::CONTENT_TYPE, "audio/mpeg".to_string()),
+                (header::CONTENT_TYPE, content_type.to_string()),
                 (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
             ];
             
@@ -642,32 +1702,33 @@ async fn download_file(Path(filename): Path<String>) -> impl IntoResponse {
     }
 }
 
-// Thumbnail service
-async fn serve_thumbnail(Path(filename): Path<String>) -> impl IntoResponse {
-    let file_path = PathBuf::from("downloads").join(&filename);
-    
-    // Security check: ensure file is within downloads directory
-    if !file_path.starts_with("downloads") {
-        return Err(StatusCode::FORBIDDEN);
+// Inline playback for the player, with Range support for seeking
+async fn stream_file(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(filename): Path<String>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    // Only the MP3s of tasks are streamed
+    if !filename.ends_with(".mp3")
+        || !tasks::is_plain_filename(&filename)
+        || !state.tasks.file_in_use(&filename)
+    {
+        return Err(StatusCode::NOT_FOUND);
     }
-    
-    match fs::read(&file_path) {
-        Ok(contents) => {
-            // Determine content type based on file extension
-            let content_type = match file_path.extension().and_then(|ext| ext.to_str()) {
-                Some("jpg") | Some("jpeg") => "image/jpeg",
-                Some("png") => "image/png", 
-                Some("webp") => "image/webp",
-                _ => "image/jpeg", // Default
-            };
-            
-            let headers = [
-                (header::CONTENT_TYPE, content_type.to_string()),
-                (header::CACHE_CONTROL, "public, max-age=3600".to_string()),
-            ];
-            
-            Ok((headers, contents))
-        }
-        Err(_) => Err(StatusCode::NOT_FOUND),
+    if !identity.may_access(state.tasks.owner_of_file(&filename).as_deref()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut response = ServeFile::new(PathBuf::from(DOWNLOAD_DIR).join(&filename))
+        .oneshot(request)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_response();
+    // RFC 5987 form, titles are rarely plain ASCII
+    let disposition = format!("inline; filename*=UTF-8''{}", history::url_path_segment(&filename));
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        response.headers_mut().insert(header::CONTENT_DISPOSITION, value);
     }
+    Ok(response)
 }
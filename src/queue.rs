@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+// Bounded pool of conversion workers
+//
+// Each conversion holds a permit while yt-dlp runs; tasks waiting for a
+// permit are counted as pending so readiness can report saturation.
+pub struct ConversionQueue {
+    slots: Semaphore,
+    workers: usize,
+    max_pending: usize,
+    pending: AtomicUsize,
+}
+
+impl ConversionQueue {
+    pub fn new(workers: usize, max_pending: usize) -> Self {
+        ConversionQueue {
+            slots: Semaphore::new(workers),
+            workers,
+            max_pending,
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    // Wait for a free worker slot
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let _pending = PendingGuard(&self.pending);
+        self.slots
+            .acquire()
+            .await
+            .expect("conversion semaphore is never closed")
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn running(&self) -> usize {
+        self.workers - self.slots.available_permits()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    pub fn max_pending(&self) -> usize {
+        self.max_pending
+    }
+
+    // All workers are busy and the backlog has reached its limit
+    pub fn is_saturated(&self) -> bool {
+        self.slots.available_permits() == 0 && self.pending() >= self.max_pending
+    }
+}
+
+// Decrements the pending counter even if the waiting task is cancelled
+struct PendingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+// Coordinates graceful shutdown between the listeners and running conversions
+//
+// Shutdown happens in stages: `draining` stops new conversions from being
+// accepted, `abort` tells conversions that outlived the deadline to kill
+// yt-dlp and clean up and webhook deliveries to stop retrying, and
+// `listeners` finally stops the HTTP(S) servers.
+#[derive(Clone)]
+pub struct Shutdown {
+    draining: CancellationToken,
+    abort: CancellationToken,
+    listeners: CancellationToken,
+    conversions: TaskTracker,
+    deliveries: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown {
+            draining: CancellationToken::new(),
+            abort: CancellationToken::new(),
+            listeners: CancellationToken::new(),
+            conversions: TaskTracker::new(),
+            deliveries: TaskTracker::new(),
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.is_cancelled()
+    }
+
+    // Cancelled once shutdown starts and no new conversion may start
+    pub fn draining_token(&self) -> CancellationToken {
+        self.draining.clone()
+    }
+
+    // Cancelled once running conversions must stop immediately
+    pub fn abort_token(&self) -> CancellationToken {
+        self.abort.clone()
+    }
+
+    // Cancelled once the listeners should stop accepting connections
+    pub fn listeners_token(&self) -> CancellationToken {
+        self.listeners.clone()
+    }
+
+    // Track a conversion so shutdown can wait for it
+    pub fn spawn_conversion<F>(&self, future: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.conversions.spawn(future);
+    }
+
+    // Track a webhook delivery so shutdown can wait for it too
+    pub fn spawn_delivery<F>(&self, future: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.deliveries.spawn(future);
+    }
+
+    // Stop accepting conversions, wait up to `deadline` for running ones and
+    // then for the webhooks they notify, abort the rest, then release the
+    // listeners
+    pub async fn drain(&self, deadline: Duration) {
+        let until = tokio::time::Instant::now() + deadline;
+        self.draining.cancel();
+        self.conversions.close();
+        self.deliveries.close();
+
+        let running = self.conversions.len();
+        if running > 0 {
+            println!(
+                "⏳ Waiting up to {}s for {} conversion(s) to finish...",
+                deadline.as_secs(),
+                running
+            );
+        }
+
+        if tokio::time::timeout_at(until, self.conversions.wait())
+            .await
+            .is_err()
+        {
+            println!(
+                "🛑 Deadline reached, aborting {} conversion(s)",
+                self.conversions.len()
+            );
+            self.abort.cancel();
+            self.conversions.wait().await;
+        }
+
+        // Finished and aborted conversions have started their deliveries by now
+        let pending = self.deliveries.len();
+        if pending > 0 {
+            println!("⏳ Waiting for {} webhook delivery(ies)...", pending);
+        }
+        if tokio::time::timeout_at(until, self.deliveries.wait())
+            .await
+            .is_err()
+        {
+            // Deliveries finish the attempt in flight, then stop retrying
+            self.abort.cancel();
+            self.deliveries.wait().await;
+        }
+
+        self.listeners.cancel();
+    }
+}
+
+// Resolves on Ctrl-C, or SIGTERM on Unix
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
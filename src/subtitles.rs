@@ -0,0 +1,432 @@
+use id3::{
+    frame::{Lyrics, SynchronisedLyrics, SynchronisedLyricsType, TimestampFormat},
+    ErrorKind, Tag, TagLike, Version,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::tasks::{Artifact, ArtifactKind};
+
+// Languages accepted in one request
+const MAX_LANGUAGES: usize = 10;
+
+// Subtitles of one language, as timed lines
+#[derive(Debug, Clone)]
+pub struct Subtitle {
+    // Code as given to yt-dlp, `en` or `pt-BR`
+    pub language: String,
+    pub cues: Vec<Cue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cue {
+    // Seconds from the start of the audio
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+// Subtitle languages from a comma- or space-separated list; blank means none
+pub fn parse_languages(value: Option<String>) -> Result<Vec<String>, String> {
+    let mut languages: Vec<String> = Vec::new();
+    for language in value
+        .unwrap_or_default()
+        .split([',', ' '])
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+    {
+        // yt-dlp reads the list as regular expressions, so only plain codes are passed on
+        let valid = language.len() <= 20
+            && language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err(format!("Invalid subtitle language: {}", language));
+        }
+        if !languages
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(language))
+        {
+            languages.push(language.to_string());
+        }
+    }
+    if languages.len() > MAX_LANGUAGES {
+        return Err(format!(
+            "At most {} subtitle languages can be requested",
+            MAX_LANGUAGES
+        ));
+    }
+    Ok(languages)
+}
+
+// yt-dlp arguments fetching the subtitles as `<stem>.<language>.srt`
+pub fn yt_dlp_args(languages: &[String], auto_generated: bool) -> Vec<String> {
+    let mut args = vec!["--write-subs".to_string()];
+    // Manual subtitles are preferred where both exist
+    if auto_generated {
+        args.push("--write-auto-subs".to_string());
+    }
+    args.extend([
+        "--sub-langs".to_string(),
+        languages.join(","),
+        "--convert-subs".to_string(),
+        "srt".to_string(),
+    ]);
+    args
+}
+
+// Read the SRT files yt-dlp wrote next to `<stem>.mp3` in `dir` and add an LRC
+// sidecar for each language
+//
+// Returns the parsed subtitles with the SRT and LRC files as artifacts.
+pub fn sidecars(
+    dir: &Path,
+    audio_filename: &str,
+    title: Option<&str>,
+) -> Result<(Vec<Subtitle>, Vec<Artifact>), String> {
+    let stem = audio_filename
+        .strip_suffix(".mp3")
+        .unwrap_or(audio_filename);
+    let prefix = format!("{}.", stem);
+
+    let mut found: Vec<(String, String)> = fs::read_dir(dir)
+        .map_err(|e| format!("Cannot list {}: {}", dir.display(), e))?
+        .flatten()
+        .filter_map(|entry| {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            let language = filename
+                .strip_prefix(&prefix)?
+                .strip_suffix(".srt")?
+                .to_string();
+            Some((language, filename))
+        })
+        .collect();
+    found.sort();
+
+    let mut subtitles = Vec::new();
+    let mut artifacts = Vec::new();
+    for (language, srt) in found {
+        let contents = fs::read_to_string(dir.join(&srt))
+            .map_err(|e| format!("Cannot read subtitles {}: {}", srt, e))?;
+        let cues = parse_srt(&contents);
+        let lrc = format!("{}.{}.lrc", stem, language);
+        fs::write(dir.join(&lrc), lrc_contents(&cues, title, &language))
+            .map_err(|e| format!("Cannot write lyrics {}: {}", lrc, e))?;
+
+        for (kind, filename) in [(ArtifactKind::Subtitles, srt), (ArtifactKind::Lyrics, lrc)] {
+            artifacts.push(Artifact {
+                kind,
+                filename,
+                title: None,
+                track: None,
+                duration: None,
+                language: Some(language.clone()),
+            });
+        }
+        subtitles.push(Subtitle { language, cues });
+    }
+    Ok((subtitles, artifacts))
+}
+
+// Embed the subtitles of every language as unsynchronised (USLT) and
+// synchronised (SYLT) lyrics
+//
+// With a range, only the cues within it are written, timed from its start,
+// for a track cut from the full audio.
+pub async fn embed(
+    path: PathBuf,
+    subtitles: &[Subtitle],
+    range: Option<(f64, f64)>,
+) -> Result<(), String> {
+    let subtitles: Vec<Subtitle> = match range {
+        Some((start, end)) => subtitles
+            .iter()
+            .map(|subtitle| Subtitle {
+                language: subtitle.language.clone(),
+                cues: subtitle
+                    .cues
+                    .iter()
+                    .filter(|cue| cue.end > start && cue.start < end)
+                    .map(|cue| Cue {
+                        start: (cue.start - start).max(0.0),
+                        end: cue.end.min(end) - start,
+                        text: cue.text.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        None => subtitles.to_vec(),
+    };
+    tokio::task::spawn_blocking(move || write_lyrics(&path, &subtitles))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn write_lyrics(path: &Path, subtitles: &[Subtitle]) -> Result<(), String> {
+    let mut tag = match Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(format!("Cannot read tags of {}: {}", path.display(), e)),
+    };
+    tag.remove("USLT");
+    tag.remove("SYLT");
+    for subtitle in subtitles
+        .iter()
+        .filter(|subtitle| !subtitle.cues.is_empty())
+    {
+        let lang = iso_639_2(&subtitle.language);
+        tag.add_frame(Lyrics {
+            lang: lang.clone(),
+            description: subtitle.language.clone(),
+            text: subtitle
+                .cues
+                .iter()
+                .map(|cue| cue.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        });
+        tag.add_frame(SynchronisedLyrics {
+            lang,
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: subtitle.language.clone(),
+            content: subtitle
+                .cues
+                .iter()
+                .map(|cue| ((cue.start * 1000.0).round() as u32, cue.text.clone()))
+                .collect(),
+        });
+    }
+    // Same version as the tags written by ffmpeg
+    tag.write_to_path(path, Version::Id3v23)
+        .map_err(|e| format!("Cannot embed lyrics in {}: {}", path.display(), e))
+}
+
+// Cues of an SRT file, with markup removed
+//
+// Auto-generated captions repeat the previous line at the start of every
+// cue so it scrolls up; repeated lines are dropped.
+fn parse_srt(contents: &str) -> Vec<Cue> {
+    let mut cues: Vec<Cue> = Vec::new();
+    let mut previous: Vec<String> = Vec::new();
+    let contents = contents.replace("\r\n", "\n");
+    for block in contents.split("\n\n") {
+        let mut lines = block
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        let (Some(start), Some(end)) = (parse_time(start), parse_time(end)) else {
+            continue;
+        };
+
+        let text: Vec<String> = lines
+            .map(strip_markup)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let new: Vec<&String> = text
+            .iter()
+            .filter(|line| !previous.contains(line))
+            .collect();
+        if !new.is_empty() {
+            cues.push(Cue {
+                start,
+                end,
+                text: new
+                    .iter()
+                    .map(|line| line.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            });
+        }
+        if !text.is_empty() {
+            previous = text;
+        }
+    }
+    cues
+}
+
+// `HH:MM:SS,mmm` in seconds; extra settings after the time are ignored
+fn parse_time(value: &str) -> Option<f64> {
+    let value = value.split_whitespace().next()?.replace(',', ".");
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+// Drop HTML-style tags and `{...}` styling overrides
+fn strip_markup(line: &str) -> String {
+    let mut text = String::new();
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, c) => text.push(c),
+            (Some(end), c) if c == end => closing = None,
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// LRC lyrics with a blank line where a cue ends before the next one starts
+fn lrc_contents(cues: &[Cue], title: Option<&str>, language: &str) -> String {
+    let mut contents = String::new();
+    if let Some(title) = title {
+        contents.push_str(&format!("[ti:{}]\n", title));
+    }
+    contents.push_str(&format!("[la:{}]\n", language));
+    for (index, cue) in cues.iter().enumerate() {
+        contents.push_str(&format!("{}{}\n", lrc_time(cue.start), cue.text));
+        let next = cues.get(index + 1).map(|next| next.start);
+        if next.is_none_or(|next| next > cue.end) {
+            contents.push_str(&format!("{}\n", lrc_time(cue.end)));
+        }
+    }
+    contents
+}
+
+// `[mm:ss.xx]`, minutes may exceed 59
+fn lrc_time(seconds: f64) -> String {
+    let hundredths = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "[{:02}:{:02}.{:02}]",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+// ID3 frames need a three-letter ISO 639-2 code
+fn iso_639_2(language: &str) -> String {
+    let base = language
+        .split('-')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let code = match base.as_str() {
+        "ar" => "ara",
+        "cs" => "ces",
+        "da" => "dan",
+        "de" => "deu",
+        "el" => "ell",
+        "en" => "eng",
+        "es" => "spa",
+        "fi" => "fin",
+        "fr" => "fra",
+        "he" => "heb",
+        "hi" => "hin",
+        "hu" => "hun",
+        "id" => "ind",
+        "it" => "ita",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "nl" => "nld",
+        "no" | "nb" => "nor",
+        "pl" => "pol",
+        "pt" => "por",
+        "ro" => "ron",
+        "ru" => "rus",
+        "sv" => "swe",
+        "th" => "tha",
+        "tr" => "tur",
+        "uk" => "ukr",
+        "vi" => "vie",
+        "zh" => "zho",
+        code if code.len() == 3 => code,
+        _ => "und",
+    };
+    code.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_languages_deduplicates_and_validates() {
+        assert_eq!(
+            parse_languages(Some(" en, de en-US EN ,".to_string())).unwrap(),
+            ["en", "de", "en-US"]
+        );
+        assert!(parse_languages(None).unwrap().is_empty());
+        assert!(parse_languages(Some("en.*".to_string())).is_err());
+        let many = (0..=MAX_LANGUAGES)
+            .map(|i| format!("l{}", i))
+            .collect::<Vec<_>>();
+        assert!(parse_languages(Some(many.join(","))).is_err());
+    }
+
+    #[test]
+    fn parse_srt_reads_cues_and_strips_markup() {
+        let srt = "1\r\n00:00:01,500 --> 00:00:03,000 X1:0\r\n<i>Hello</i> {\\an8}world\r\n\r\n\
+                   2\r\n01:02:03,250 --> 01:02:04,000\r\nSecond\r\nline\r\n\r\n\
+                   3\r\nnot a timing\r\nskipped\r\n";
+        let cues = parse_srt(srt);
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start, cues[0].end), (1.5, 3.0));
+        assert_eq!(cues[0].text, "Hello world");
+        assert_eq!(cues[1].start, 3723.25);
+        assert_eq!(cues[1].text, "Second line");
+    }
+
+    #[test]
+    fn parse_srt_drops_scrolling_repeats() {
+        let srt = "1\n00:00:00,000 --> 00:00:02,000\nfirst\n\n\
+                   2\n00:00:02,000 --> 00:00:04,000\nfirst\nsecond\n\n\
+                   3\n00:00:04,000 --> 00:00:05,000\nsecond\n";
+        let texts: Vec<String> = parse_srt(srt).into_iter().map(|cue| cue.text).collect();
+        assert_eq!(texts, ["first", "second"]);
+    }
+
+    #[test]
+    fn parse_time_rejects_garbage() {
+        assert_eq!(parse_time("00:01:02,003"), Some(62.003));
+        assert_eq!(parse_time(" 00:00:10.5 line:90%"), Some(10.5));
+        assert_eq!(parse_time("00:aa:10,000"), None);
+        assert_eq!(parse_time(""), None);
+    }
+
+    #[test]
+    fn lrc_contents_marks_gaps() {
+        let cues = [
+            Cue {
+                start: 1.0,
+                end: 2.0,
+                text: "a".to_string(),
+            },
+            Cue {
+                start: 2.0,
+                end: 3.0,
+                text: "b".to_string(),
+            },
+            Cue {
+                start: 65.5,
+                end: 66.0,
+                text: "c".to_string(),
+            },
+        ];
+        assert_eq!(
+            lrc_contents(&cues, Some("Song"), "en"),
+            "[ti:Song]\n[la:en]\n[00:01.00]a\n[00:02.00]b\n[00:03.00]\n[01:05.50]c\n[01:06.00]\n"
+        );
+    }
+
+    #[test]
+    fn iso_639_2_maps_regional_codes() {
+        assert_eq!(iso_639_2("en-US"), "eng");
+        assert_eq!(iso_639_2("NB"), "nor");
+        assert_eq!(iso_639_2("haw"), "haw");
+        assert_eq!(iso_639_2("xx"), "und");
+    }
+}
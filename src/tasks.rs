@@ -0,0 +1,463 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    failures::{Failure, FailureKind},
+    loudness::Loudness,
+};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Task status enum
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TaskStatus {
+    Processing,
+    Completed(String, Option<String>), // Store audio file path and thumbnail file path
+    Failed(String),                    // Store error message
+}
+
+// A task and who submitted it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub status: TaskStatus,
+    // Source video URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // Video title, known once the conversion completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // Unix timestamps of submission and of the final status
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    // Client certificate identity of the submitter, None for anonymous clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    // Webhook notified when this task finishes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    // Scheme and host the task was submitted through, the base of the download
+    // URL sent to webhooks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    // Webhook delivery attempts, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deliveries: Vec<DeliveryAttempt>,
+    #[serde(default)]
+    pub options: ConvertOptions,
+    // Output files besides the main audio file and thumbnail
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+    // Measured loudness when the audio was normalized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<Loudness>,
+    // Length of the video in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    // Named podcast feed the task is published in, besides its owner's feed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    // Classified cause of a failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<FailureKind>,
+    // Conversion runs, oldest first; retries add one each
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<ConversionAttempt>,
+}
+
+impl TaskRecord {
+    pub fn new(url: Option<String>, owner: Option<String>, callback_url: Option<String>) -> Self {
+        TaskRecord {
+            status: TaskStatus::Processing,
+            url,
+            title: None,
+            created_at: unix_now(),
+            finished_at: None,
+            owner,
+            callback_url,
+            base_url: None,
+            deliveries: Vec::new(),
+            options: ConvertOptions::default(),
+            artifacts: Vec::new(),
+            loudness: None,
+            duration: None,
+            collection: None,
+            error_code: None,
+            attempts: Vec::new(),
+        }
+    }
+
+    // Every file produced by the task
+    pub fn files(&self) -> Vec<&str> {
+        let mut files = Vec::new();
+        if let TaskStatus::Completed(audio, thumbnail) = &self.status {
+            files.push(audio.as_str());
+            files.extend(thumbnail.as_deref());
+        }
+        files.extend(self.artifacts.iter().map(|artifact| artifact.filename.as_str()));
+        files
+    }
+}
+
+// How a URL is converted, chosen at submission
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConvertOptions {
+    // One track per chapter instead of a single file
+    #[serde(default)]
+    pub split_chapters: bool,
+    // EBU R128 loudness normalization after extraction
+    #[serde(default)]
+    pub normalize: bool,
+    // Centre square crop of the thumbnail
+    #[serde(default)]
+    pub square_thumbnail: bool,
+    // Subtitle languages fetched as SRT and LRC sidecars, none when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subtitles: Vec<String>,
+    // Use auto-generated captions where no manual subtitles exist
+    #[serde(default)]
+    pub auto_subtitles: bool,
+    // Embed the subtitles in the MP3 as lyrics tags
+    #[serde(default)]
+    pub embed_lyrics: bool,
+    // Proxy URL for this task instead of the global one; may hold a password,
+    // so it is kept in the task file but never returned by the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    // yt-dlp format selection of the source, best audio when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+// Output file of a task besides the main audio file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    pub kind: ArtifactKind,
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // Position within the split video, starting at 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<u32>,
+    // Length in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    // Subtitle language of a sidecar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    Track,
+    Archive,
+    Playlist,
+    // SRT sidecar
+    Subtitles,
+    // LRC sidecar
+    Lyrics,
+    // Peaks JSON of an MP3, drawn by the player
+    Waveform,
+}
+
+// One run of a task's conversion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversionAttempt {
+    pub attempt: u32,
+    // Unix timestamps of the start and end of the run
+    pub started_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    // Why the run failed, None when it succeeded or is still running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<FailureKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// One attempt to deliver a webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    // Shared by the retries of one delivery
+    pub delivery_id: String,
+    pub url: String,
+    pub event: String,
+    pub attempt: u32,
+    // Unix timestamp of the attempt
+    pub at: u64,
+    // HTTP status of the response, None when no response was received
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+// URLs submitted together in one request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    // Tasks in submission order
+    pub task_ids: Vec<String>,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+// Entry in the task file; older versions stored the bare status
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTask {
+    Record(Box<TaskRecord>),
+    Status(TaskStatus),
+}
+
+impl From<StoredTask> for TaskRecord {
+    fn from(stored: StoredTask) -> Self {
+        match stored {
+            StoredTask::Record(record) => *record,
+            StoredTask::Status(status) => TaskRecord {
+                status,
+                ..TaskRecord::new(None, None, None)
+            },
+        }
+    }
+}
+
+// Task status management, persisted to a JSON file on every change
+//
+// Batches are kept in `batches.json` next to the task file.
+pub struct TaskStore {
+    path: PathBuf,
+    tasks: Mutex<HashMap<String, TaskRecord>>,
+    batches_path: PathBuf,
+    batches: Mutex<HashMap<String, Batch>>,
+}
+
+impl TaskStore {
+    // Load tasks saved by a previous run
+    //
+    // Tasks still marked as processing were interrupted by a crash and can
+    // never finish, so they are recorded as failed.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let stored: HashMap<String, StoredTask> = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                eprintln!("⚠️  Ignoring unreadable task file {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let mut tasks: HashMap<String, TaskRecord> =
+            stored.into_iter().map(|(id, task)| (id, task.into())).collect();
+
+        for record in tasks.values_mut() {
+            if matches!(record.status, TaskStatus::Processing) {
+                let error = "Interrupted by server restart".to_string();
+                if let Some(attempt) = record.attempts.last_mut().filter(|last| last.finished_at.is_none()) {
+                    attempt.finished_at = Some(unix_now());
+                    attempt.error_code = Some(FailureKind::Cancelled);
+                    attempt.error = Some(error.clone());
+                }
+                record.status = TaskStatus::Failed(error);
+                record.error_code = Some(FailureKind::Cancelled);
+            }
+        }
+
+        let batches_path = path.with_file_name("batches.json");
+        let batches = match fs::read(&batches_path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                eprintln!("⚠️  Ignoring unreadable batch file {}: {}", batches_path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        TaskStore {
+            path,
+            tasks: Mutex::new(tasks),
+            batches_path,
+            batches: Mutex::new(batches),
+        }
+    }
+
+    pub fn get(&self, task_id: &str) -> Option<TaskRecord> {
+        self.tasks.lock().unwrap().get(task_id).cloned()
+    }
+
+    // Register a new task
+    pub fn create(&self, task_id: &str, record: TaskRecord) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.insert(task_id.to_string(), record);
+        self.persist(&tasks);
+    }
+
+    // Update a task's status and persist the new state
+    pub fn set(&self, task_id: &str, status: TaskStatus) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let record = tasks
+            .entry(task_id.to_string())
+            .or_insert_with(|| TaskRecord::new(None, None, None));
+        if let TaskStatus::Completed(audio_filename, _) = &status {
+            record.title = Some(title_from_filename(audio_filename));
+        }
+        record.finished_at = match status {
+            TaskStatus::Processing => None,
+            _ => Some(unix_now()),
+        };
+        record.status = status;
+        self.persist(&tasks);
+    }
+
+    // Snapshot of every task with its ID
+    pub fn list(&self) -> Vec<(String, TaskRecord)> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.iter().map(|(id, record)| (id.clone(), record.clone())).collect()
+    }
+
+    // Forget a task, returning its last record
+    pub fn remove(&self, task_id: &str) -> Option<TaskRecord> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let record = tasks.remove(task_id)?;
+        self.persist(&tasks);
+        Some(record)
+    }
+
+    // Whether any remaining task produced the file
+    pub fn file_in_use(&self, filename: &str) -> bool {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.values().any(|record| record.files().contains(&filename))
+    }
+
+    // Put a failed task back to processing for another run
+    //
+    // Returns false unless the task exists and has failed, so a task is never
+    // converted twice at the same time.
+    pub fn reopen(&self, task_id: &str) -> bool {
+        let mut tasks = self.tasks.lock().unwrap();
+        let Some(record) = tasks.get_mut(task_id) else {
+            return false;
+        };
+        if !matches!(record.status, TaskStatus::Failed(_)) {
+            return false;
+        }
+        record.status = TaskStatus::Processing;
+        record.finished_at = None;
+        record.error_code = None;
+        self.persist(&tasks);
+        true
+    }
+
+    // Record the start of a conversion run
+    pub fn start_attempt(&self, task_id: &str) {
+        self.update(task_id, |record| {
+            let attempt = record.attempts.len() as u32 + 1;
+            record.attempts.push(ConversionAttempt {
+                attempt,
+                started_at: unix_now(),
+                finished_at: None,
+                error_code: None,
+                error: None,
+            });
+        });
+    }
+
+    // Record the end of the current conversion run, with its failure if any
+    pub fn end_attempt(&self, task_id: &str, failure: Option<&Failure>) {
+        self.update(task_id, |record| {
+            if let Some(attempt) = record.attempts.last_mut() {
+                attempt.finished_at = Some(unix_now());
+                attempt.error_code = failure.map(|failure| failure.kind);
+                attempt.error = failure.map(|failure| failure.message.clone());
+            }
+        });
+    }
+
+    // Change a task in place and persist it
+    pub fn update(&self, task_id: &str, change: impl FnOnce(&mut TaskRecord)) {
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(record) = tasks.get_mut(task_id) {
+            change(record);
+            self.persist(&tasks);
+        }
+    }
+
+    // Register a batch of already created tasks
+    pub fn create_batch(&self, batch_id: &str, batch: Batch) {
+        let mut batches = self.batches.lock().unwrap();
+        batches.insert(batch_id.to_string(), batch);
+        if let Err(e) = write_atomic(&self.batches_path, &*batches) {
+            eprintln!("⚠️  Failed to save batches to {}: {}", self.batches_path.display(), e);
+        }
+    }
+
+    pub fn batch(&self, batch_id: &str) -> Option<Batch> {
+        self.batches.lock().unwrap().get(batch_id).cloned()
+    }
+
+    // Append a webhook delivery attempt to the task's history
+    pub fn record_delivery(&self, task_id: &str, attempt: DeliveryAttempt) {
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(record) = tasks.get_mut(task_id) {
+            record.deliveries.push(attempt);
+            self.persist(&tasks);
+        }
+    }
+
+    // Owner of the task that produced a downloaded or thumbnail file
+    pub fn owner_of_file(&self, filename: &str) -> Option<String> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks
+            .values()
+            .find(|record| record.files().contains(&filename))
+            .and_then(|record| record.owner.clone())
+    }
+
+    fn persist(&self, tasks: &HashMap<String, TaskRecord>) {
+        if let Err(e) = write_atomic(&self.path, tasks) {
+            eprintln!("⚠️  Failed to save tasks to {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let tasks = self.tasks.lock().unwrap();
+        write_atomic(&self.path, &*tasks)?;
+        let batches = self.batches.lock().unwrap();
+        write_atomic(&self.batches_path, &*batches)
+    }
+}
+
+// A bare file name, without directories or `..` that would leave the downloads
+pub fn is_plain_filename(filename: &str) -> bool {
+    Path::new(filename).file_name() == Some(OsStr::new(filename))
+}
+
+// Output files are named `<title>_<timestamp>.<ext>`
+fn title_from_filename(filename: &str) -> String {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    match stem.rsplit_once('_') {
+        Some((title, suffix)) if !title.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) => {
+            title.to_string()
+        }
+        _ => stem.to_string(),
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+// Write to a temporary file first so a crash never leaves a truncated file
+fn write_atomic<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), String> {
+    let contents = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+}
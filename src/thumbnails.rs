@@ -0,0 +1,296 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView, ImageFormat,
+    ImageReader,
+};
+use serde::Deserialize;
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path as FsPath, PathBuf},
+    time::UNIX_EPOCH,
+};
+use uuid::Uuid;
+
+use crate::{
+    client_auth::ClientIdentity,
+    config::{ThumbnailFormat, DOWNLOAD_DIR},
+    tasks, AppState,
+};
+
+const JPEG_QUALITY: u8 = 88;
+// Resized variants are cached here, inside the download directory
+const VARIANTS_DIR: &str = "thumbnails";
+// Luma below which a pixel belongs to a black bar
+const BAR_LUMA: u8 = 24;
+// Share of a row or column that must be dark for it to count as a bar
+const BAR_COVERAGE: f64 = 0.98;
+// Thumbnails are cached by browsers for a day
+const MAX_AGE_SECS: u32 = 86400;
+
+#[derive(Deserialize)]
+pub struct ThumbnailQuery {
+    // Wanted longest edge; the nearest configured size is served
+    #[serde(default)]
+    size: Option<u32>,
+}
+
+// Rewrite a thumbnail downloaded to `dir` as JPEG or PNG without black bars,
+// optionally cropped to a centred square
+//
+// Returns the new file name; the original is removed when it differs.
+pub fn normalize(
+    dir: &FsPath,
+    filename: &str,
+    format: ThumbnailFormat,
+    square: bool,
+) -> Result<String, String> {
+    let mut image = decode(&dir.join(filename))?;
+    image = trim_letterbox(image);
+    if square {
+        let (width, height) = image.dimensions();
+        let side = width.min(height);
+        image = image.crop_imm((width - side) / 2, (height - side) / 2, side, side);
+    }
+
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let output = format!("{}.{}", stem, format.extension());
+    encode(&image, &dir.join(&output), format)?;
+    if output != filename {
+        let _ = fs::remove_file(dir.join(filename));
+    }
+    Ok(output)
+}
+
+// GET /thumbnail/:filename, optionally resized with `?size=`
+pub async fn serve_thumbnail(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(filename): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    // Only files of a task, named without any directory: the resized variant
+    // is written under a name derived from this one
+    if !tasks::is_plain_filename(&filename) || !state.tasks.file_in_use(&filename) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let file_path = PathBuf::from(DOWNLOAD_DIR).join(&filename);
+    let owner = state.tasks.owner_of_file(&filename);
+    if !identity.may_access(owner.as_deref()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if !file_path.is_file() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let path = match query.size {
+        Some(size) => {
+            let sizes = &state.config.thumbnail_sizes;
+            let size = sizes
+                .iter()
+                .copied()
+                .find(|&configured| configured >= size)
+                .or(sizes.last().copied())
+                .unwrap_or(size);
+            let format = state.config.thumbnail_format;
+            let source = file_path.clone();
+            let name = filename.clone();
+            tokio::task::spawn_blocking(move || variant(&source, &name, size, format))
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .unwrap_or_else(|e| {
+                    eprintln!("⚠️  {}", e);
+                    file_path
+                })
+        }
+        None => file_path,
+    };
+
+    let metadata = fs::metadata(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_millis());
+    let etag = format!("\"{:x}-{:x}\"", modified, metadata.len());
+    // Thumbnails of a user's tasks must not be kept by shared caches
+    let cache_control = format!(
+        "{}, max-age={}",
+        if owner.is_some() { "private" } else { "public" },
+        MAX_AGE_SECS
+    );
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+    if not_modified {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, etag), (header::CACHE_CONTROL, cache_control)],
+        )
+            .into_response());
+    }
+
+    let contents = fs::read(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+    // Determine content type based on file extension
+    let content_type = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg", // Default
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CACHE_CONTROL, cache_control),
+            (header::ETAG, etag),
+        ],
+        contents,
+    )
+        .into_response())
+}
+
+// Remove the cached variants of a thumbnail
+pub fn remove_variants(filename: &str) {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let prefix = format!("{}_", stem);
+    let dir = FsPath::new(DOWNLOAD_DIR).join(VARIANTS_DIR);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_variant = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.split_once('.'))
+            .is_some_and(|(size, _)| !size.is_empty() && size.bytes().all(|b| b.is_ascii_digit()));
+        if is_variant {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+// Path of the thumbnail scaled to fit `size`, written on first use
+//
+// Images already within the size are served as they are.
+fn variant(
+    source: &FsPath,
+    filename: &str,
+    size: u32,
+    format: ThumbnailFormat,
+) -> Result<PathBuf, String> {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let dir = FsPath::new(DOWNLOAD_DIR).join(VARIANTS_DIR);
+    let path = dir.join(format!("{}_{}.{}", stem, size, format.extension()));
+
+    // A cached variant is stale once the source is rewritten
+    let modified = |path: &FsPath| fs::metadata(path).and_then(|metadata| metadata.modified());
+    if let (Ok(cached), Ok(original)) = (modified(&path), modified(source)) {
+        if cached >= original {
+            return Ok(path);
+        }
+    }
+
+    let (width, height) = ImageReader::open(source)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())
+        .and_then(|reader| reader.into_dimensions().map_err(|e| e.to_string()))
+        .map_err(|e| format!("Cannot read thumbnail {}: {}", source.display(), e))?;
+    if width.max(height) <= size {
+        return Ok(source.to_path_buf());
+    }
+    let image = decode(source)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    encode(
+        &image.resize(size, size, FilterType::Lanczos3),
+        &path,
+        format,
+    )?;
+    Ok(path)
+}
+
+fn decode(path: &FsPath) -> Result<DynamicImage, String> {
+    ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| format!("Cannot read thumbnail {}: {}", path.display(), e))?
+        .decode()
+        .map_err(|e| format!("Cannot decode thumbnail {}: {}", path.display(), e))
+}
+
+// Write through a temporary file so readers never see a partial image
+fn encode(image: &DynamicImage, path: &FsPath, format: ThumbnailFormat) -> Result<(), String> {
+    let temp = PathBuf::from(format!("{}.{}.tmp", path.display(), Uuid::new_v4()));
+    let written = File::create(&temp)
+        .map_err(|e| e.to_string())
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            match format {
+                // JPEG has no alpha channel
+                ThumbnailFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                    .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY)),
+                ThumbnailFormat::Png => image.write_to(&mut writer, ImageFormat::Png),
+            }
+            .map_err(|e| e.to_string())
+        })
+        .and_then(|()| fs::rename(&temp, path).map_err(|e| e.to_string()));
+    written.map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        format!("Cannot write thumbnail {}: {}", path.display(), e)
+    })
+}
+
+// Crop black bars above, below and beside the picture
+//
+// Mostly dark images are left alone rather than cropped to a sliver.
+fn trim_letterbox(image: DynamicImage) -> DynamicImage {
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+    if width == 0 || height == 0 {
+        return image;
+    }
+    let dark_row = |y: u32, from: u32, to: u32| {
+        let dark = (from..to)
+            .filter(|&x| luma.get_pixel(x, y)[0] < BAR_LUMA)
+            .count();
+        dark as f64 >= BAR_COVERAGE * f64::from(to - from)
+    };
+    let dark_column = |x: u32, from: u32, to: u32| {
+        let dark = (from..to)
+            .filter(|&y| luma.get_pixel(x, y)[0] < BAR_LUMA)
+            .count();
+        dark as f64 >= BAR_COVERAGE * f64::from(to - from)
+    };
+
+    let top = (0..height)
+        .find(|&y| !dark_row(y, 0, width))
+        .unwrap_or(height);
+    if top == height {
+        return image;
+    }
+    let bottom = (top..height)
+        .rev()
+        .find(|&y| !dark_row(y, 0, width))
+        .map_or(height, |y| y + 1);
+    let left = (0..width)
+        .find(|&x| !dark_column(x, top, bottom))
+        .unwrap_or(0);
+    let right = (left..width)
+        .rev()
+        .find(|&x| !dark_column(x, top, bottom))
+        .map_or(width, |x| x + 1);
+
+    let (trimmed_width, trimmed_height) = (right - left, bottom - top);
+    if (trimmed_width, trimmed_height) == (width, height)
+        || trimmed_width < width / 2
+        || trimmed_height < height / 2
+    {
+        return image;
+    }
+    image.crop_imm(left, top, trimmed_width, trimmed_height)
+}
@@ -0,0 +1,363 @@
+use axum_server::tls_rustls::RustlsConfig;
+use notify::{RecursiveMode, Watcher};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use x509_parser::{certificate::X509Certificate, prelude::FromDer};
+use yt_mp3_service::{
+    certs::{self, KeyType, LeafOptions, Subject},
+    lan::InterfaceFilter,
+};
+
+use crate::{
+    acme::{self, CertResolver, Challenges},
+    client_auth,
+    config::Config,
+};
+
+// Quiet period after a file change before reloading, so that a renewal
+// writing the certificate and key separately is picked up as one update
+const RELOAD_DEBOUNCE: Duration = Duration::from_secs(2);
+
+// How often the background task checks whether renewal is due
+const RENEW_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+// Summary of the certificate currently served by the HTTPS listener
+#[derive(Debug, Clone)]
+pub struct CertInfo {
+    pub subject: String,
+    pub not_before: OffsetDateTime,
+    pub not_after: OffsetDateTime,
+    pub fingerprint: String,
+}
+
+impl CertInfo {
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        let (_, cert) = X509Certificate::from_der(der)
+            .map_err(|e| format!("Cannot parse certificate: {}", e))?;
+        let validity = cert.validity();
+        Ok(CertInfo {
+            subject: cert.subject().to_string(),
+            not_before: validity.not_before.to_datetime(),
+            not_after: validity.not_after.to_datetime(),
+            fingerprint: certs::fingerprint(der),
+        })
+    }
+
+    // Whole days until the certificate expires (negative once expired)
+    pub fn days_remaining(&self) -> i64 {
+        (self.not_after - OffsetDateTime::now_utc()).whole_days()
+    }
+}
+
+// Build a rustls server configuration from the configured certificate and key
+//
+// The pair is validated first: the leaf must parse and must carry the
+// public key matching the private key. ACME TLS-ALPN-01 validation
+// connections are answered from `challenges`.
+pub fn load_server_config(
+    config: &Config,
+    challenges: &Arc<Challenges>,
+) -> Result<(ServerConfig, CertInfo), String> {
+    let (cert_path, key_path) = (config.cert_path.as_path(), config.key_path.as_path());
+    let chain = read_cert_chain(cert_path)?;
+    let leaf = chain
+        .first()
+        .ok_or_else(|| format!("No certificate found in {}", cert_path.display()))?;
+    let info = CertInfo::from_der(&leaf.0)?;
+
+    let key = read_private_key(key_path)?;
+    check_key_matches(&leaf.0, &key)?;
+
+    let resolver = CertResolver::new(chain, &key, challenges.clone())?;
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(client_auth::verifier(config)?)
+        .with_cert_resolver(Arc::new(resolver));
+    server_config.alpn_protocols = vec![
+        b"h2".to_vec(),
+        b"http/1.1".to_vec(),
+        acme::ACME_TLS_ALPN.to_vec(),
+    ];
+
+    Ok((server_config, info))
+}
+
+pub fn read_cert_chain(path: &Path) -> Result<Vec<Certificate>, String> {
+    let pem = fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem.as_slice()))
+        .map_err(|e| format!("Invalid PEM in {}: {}", path.display(), e))?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey, String> {
+    let pem = fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(pem.as_slice()))
+        .map_err(|e| format!("Invalid PEM in {}: {}", path.display(), e))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", path.display()))
+}
+
+// Compare the certificate's public key with the one derived from the private key
+//
+// Only PKCS#8 keys can be inspected; other encodings are left to rustls.
+fn check_key_matches(cert_der: &[u8], key: &PrivateKey) -> Result<(), String> {
+    let Ok(key_pair) = rcgen::KeyPair::from_der(&key.0) else {
+        return Ok(());
+    };
+    let (_, cert) = X509Certificate::from_der(cert_der)
+        .map_err(|e| format!("Cannot parse certificate: {}", e))?;
+    if cert.public_key().subject_public_key.data.as_ref() != key_pair.public_key_raw() {
+        return Err("Private key does not match the certificate".to_string());
+    }
+    Ok(())
+}
+
+// Reload the certificate when its files change or on SIGHUP
+//
+// A pair that fails validation is logged and ignored, so the listener keeps
+// serving the previous certificate.
+pub async fn watch_and_reload(
+    tls_config: RustlsConfig,
+    cert: Arc<RwLock<Option<CertInfo>>>,
+    config: Arc<Config>,
+    challenges: Arc<Challenges>,
+) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+
+    // Watch the parent directories: renewals usually replace files by renaming
+    let _watcher = if config.tls_watch {
+        match file_watcher(&config.cert_path, &config.key_path, tx.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                eprintln!("⚠️  Cannot watch certificate files, reload on SIGHUP only: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .expect("Failed to install SIGHUP handler");
+            while hangup.recv().await.is_some() {
+                println!("🔄 SIGHUP received, reloading certificate");
+                if tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    while rx.recv().await.is_some() {
+        // Collapse the burst of events produced by a single renewal
+        loop {
+            match tokio::time::timeout(RELOAD_DEBOUNCE, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+
+        if let Err(e) = reload(&tls_config, &cert, &config, &challenges) {
+            eprintln!("⚠️  Certificate reload failed, keeping the current one: {}", e);
+        }
+    }
+}
+
+// Validate the pair on disk and swap it into the running listener
+//
+// The client CA bundle is read again as well.
+pub fn reload(
+    tls_config: &RustlsConfig,
+    cert: &RwLock<Option<CertInfo>>,
+    config: &Config,
+    challenges: &Arc<Challenges>,
+) -> Result<CertInfo, String> {
+    let (server_config, info) = load_server_config(config, challenges)?;
+    if info.days_remaining() < 0 {
+        return Err(format!("Certificate {} has expired", config.cert_path.display()));
+    }
+
+    tls_config.reload_from_config(Arc::new(server_config));
+    println!(
+        "🔐 Certificate reloaded: {} (expires {}, SHA-256 {})",
+        info.subject, info.not_after, info.fingerprint
+    );
+    *cert.write().unwrap() = Some(info.clone());
+    Ok(info)
+}
+
+// Directory holding the local CA: next to the server certificate
+pub fn ca_dir(config: &Config) -> PathBuf {
+    config
+        .cert_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// Leaf options for automatically issued certificates
+fn auto_leaf_options(config: &Config, existing_sans: Vec<rcgen::SanType>) -> LeafOptions {
+    let mut sans = certs::loopback_sans();
+    for san in existing_sans {
+        certs::push_unique(&mut sans, san);
+    }
+    if config.cert_lan {
+        let filter = InterfaceFilter {
+            allow: config.cert_interfaces.clone(),
+            deny: config.cert_exclude_interfaces.clone(),
+        };
+        certs::add_lan_sans(&mut sans, &filter);
+    }
+
+    LeafOptions {
+        sans,
+        days: certs::DEFAULT_LEAF_DAYS,
+        key_type: KeyType::P256,
+        subject: Subject::default(),
+    }
+}
+
+// Issue a certificate from the local CA when none is configured yet
+pub fn ensure_certificate(config: &Config) -> Result<(), String> {
+    if config.cert_path.exists() && config.key_path.exists() {
+        return Ok(());
+    }
+    // Under ACME the local certificate only bridges the time until the first order
+    if !config.cert_auto && !config.acme_enabled() {
+        return Err(format!(
+            "{} or {} is missing and automatic provisioning is disabled",
+            config.cert_path.display(),
+            config.key_path.display()
+        ));
+    }
+
+    println!("🔐 No SSL certificate found, issuing one from the local CA...");
+    let options = auto_leaf_options(config, Vec::new());
+    let ca = certs::provision(&config.cert_path, &config.key_path, &ca_dir(config), &options)
+        .map_err(|e| format!("Cannot provision certificate: {}", e))?;
+    if ca.created {
+        println!("🏛️  Created local CA: {} (install it on client devices)", ca.cert_path.display());
+    }
+    println!("🌐 Certificate names: {}", certs::describe_sans(&options.sans));
+    Ok(())
+}
+
+// Periodically renew locally issued certificates that are close to expiry
+//
+// Certificates from other issuers are never replaced, only reported.
+// With ACME configured, renewal is left to the ACME task.
+pub async fn renew_periodically(
+    tls_config: RustlsConfig,
+    cert: Arc<RwLock<Option<CertInfo>>>,
+    config: Arc<Config>,
+    challenges: Arc<Challenges>,
+) {
+    if !config.cert_auto || config.acme_enabled() {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(RENEW_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let days_remaining = match cert.read().unwrap().as_ref() {
+            Some(info) => info.days_remaining(),
+            None => continue,
+        };
+        if days_remaining >= config.cert_renew_days {
+            continue;
+        }
+
+        let renewal_config = config.clone();
+        let renewed = tokio::task::spawn_blocking(move || renew(&renewal_config))
+            .await
+            .unwrap_or_else(|e| Err(format!("Renewal task failed: {}", e)));
+        match renewed {
+            Ok(true) => {
+                if let Err(e) = reload(&tls_config, &cert, &config, &challenges) {
+                    eprintln!("⚠️  Renewed certificate could not be loaded: {}", e);
+                }
+            }
+            Ok(false) => eprintln!(
+                "⚠️  Certificate expires in {} days but was not issued locally; renew it manually",
+                days_remaining
+            ),
+            Err(e) => eprintln!("⚠️  Certificate renewal failed: {}", e),
+        }
+    }
+}
+
+// Replace the certificate on disk, keeping its names; false if not ours to renew
+fn renew(config: &Config) -> Result<bool, String> {
+    let chain = read_cert_chain(&config.cert_path)?;
+    let leaf = chain
+        .first()
+        .ok_or_else(|| format!("No certificate found in {}", config.cert_path.display()))?;
+    let ca_dir = ca_dir(config);
+    if !certs::is_locally_issued(&leaf.0, &ca_dir) {
+        return Ok(false);
+    }
+
+    println!("🔄 Certificate is close to expiry, issuing a new one from the local CA...");
+    let options = auto_leaf_options(config, certs::sans_of(&leaf.0));
+    certs::provision(&config.cert_path, &config.key_path, &ca_dir, &options)
+        .map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+fn file_watcher(
+    cert_path: &Path,
+    key_path: &Path,
+    tx: mpsc::UnboundedSender<()>,
+) -> notify::Result<notify::RecommendedWatcher> {
+    let targets: Vec<PathBuf> = [cert_path, key_path]
+        .iter()
+        .filter_map(|path| fs::canonicalize(path).ok())
+        .collect();
+    let file_names: Vec<_> = targets
+        .iter()
+        .filter_map(|path| path.file_name().map(|name| name.to_owned()))
+        .collect();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        if event.kind.is_access() {
+            return;
+        }
+        let relevant = event.paths.iter().any(|path| {
+            path.file_name()
+                .is_some_and(|name| file_names.iter().any(|target| target == name))
+        });
+        if relevant {
+            let _ = tx.send(());
+        }
+    })?;
+
+    let mut dirs: Vec<&Path> = targets.iter().filter_map(|path| path.parent()).collect();
+    dirs.dedup();
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
@@ -0,0 +1,219 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::process::Command;
+
+use crate::config::Config;
+
+// Upper bound for a single version probe of an external tool
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// Directory next to the service searched before `PATH`
+const BIN_DIR: &str = "bin";
+
+// External programs the conversions run
+#[derive(Debug, Clone, Copy)]
+pub enum Tool {
+    YtDlp,
+    Ffmpeg,
+}
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::YtDlp => "yt-dlp",
+            Tool::Ffmpeg => "ffmpeg",
+        }
+    }
+
+    fn version_flag(self) -> &'static str {
+        match self {
+            Tool::YtDlp => "--version",
+            Tool::Ffmpeg => "-version",
+        }
+    }
+
+    // Oldest version known to support every option the service passes:
+    // `--match-filter` with `<=?` for yt-dlp, loudnorm's JSON output for FFmpeg
+    pub fn minimum_version(self) -> &'static str {
+        match self {
+            Tool::YtDlp => "2023.03.04",
+            Tool::Ffmpeg => "4.0",
+        }
+    }
+
+    // Version number in the first line of the version output
+    //
+    // yt-dlp prints a date (`2024.08.06`), FFmpeg `ffmpeg version 6.1.1-…`;
+    // FFmpeg builds from git (`N-112345-g…`) carry no version number.
+    fn parse_version(self, line: &str) -> Option<String> {
+        let token = match self {
+            Tool::YtDlp => line.split_whitespace().next()?,
+            Tool::Ffmpeg => line
+                .strip_prefix("ffmpeg version ")?
+                .trim_start_matches('n'),
+        };
+        let version: String = token
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        let version = version.trim_end_matches('.');
+        (!version.is_empty()).then(|| version.to_string())
+    }
+}
+
+// Result of running a tool's version command
+pub struct Probe {
+    // First line of the output
+    pub banner: String,
+    pub version: Option<String>,
+    // False when older than the minimum, None when the version is unknown
+    pub supported: Option<bool>,
+}
+
+// Resolve a tool: the configured path, then `bin/`, then `PATH`
+//
+// A configured bare name is looked up on `PATH`. Without a match the plain
+// executable name is returned, so spawning fails with a clear error.
+pub fn locate(tool: Tool, configured: Option<PathBuf>) -> PathBuf {
+    let executable = format!("{}{}", tool.name(), env::consts::EXE_SUFFIX);
+    if let Some(path) = configured {
+        let bare = path.components().count() == 1 && !path.is_file();
+        return match bare {
+            true => search_path(&path).unwrap_or(path),
+            false => path,
+        };
+    }
+    let bundled = Path::new(BIN_DIR).join(&executable);
+    if bundled.is_file() {
+        return bundled;
+    }
+    search_path(Path::new(&executable)).unwrap_or_else(|| PathBuf::from(executable))
+}
+
+fn search_path(name: &Path) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+// Run the tool's version command and compare against the minimum version
+pub async fn probe(tool: Tool, path: &Path) -> Result<Probe, String> {
+    let output = tokio::time::timeout(
+        PROBE_TIMEOUT,
+        Command::new(path)
+            .arg(tool.version_flag())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| "timed out while reading version".to_string())?
+    .map_err(|e| format!("cannot execute: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let banner = stdout.lines().next().unwrap_or("").trim().to_string();
+    let version = tool.parse_version(&banner);
+    let supported = version
+        .as_deref()
+        .map(|version| numbers(version) >= numbers(tool.minimum_version()));
+    Ok(Probe {
+        banner,
+        version,
+        supported,
+    })
+}
+
+// Check the tools once at startup; problems are logged, not fatal, since
+// readiness reports them too
+pub async fn verify(config: &Config) {
+    for (tool, path) in [
+        (Tool::YtDlp, &config.yt_dlp_path),
+        (Tool::Ffmpeg, &config.ffmpeg_path),
+    ] {
+        match probe(tool, path).await {
+            Ok(probe) => match (probe.version, probe.supported) {
+                (Some(version), Some(true)) => {
+                    println!("🔧 {} {} at {}", tool.name(), version, path.display())
+                }
+                (Some(version), _) => eprintln!(
+                    "⚠️  {} {} at {} is older than the required {}, please update it",
+                    tool.name(),
+                    version,
+                    path.display(),
+                    tool.minimum_version()
+                ),
+                (None, _) => println!(
+                    "ℹ️  {} at {} reports no version number: {}",
+                    tool.name(),
+                    path.display(),
+                    probe.banner
+                ),
+            },
+            Err(e) => eprintln!(
+                "⚠️  {} not usable at {}: {}",
+                tool.name(),
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+// `2024.08.06` as `[2024, 8, 6]`, comparable element by element
+fn numbers(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version_reads_yt_dlp_dates() {
+        let parse = |line| Tool::YtDlp.parse_version(line);
+        assert_eq!(parse("2024.08.06"), Some("2024.08.06".to_string()));
+        assert_eq!(
+            parse("2023.03.04.1 (nightly)"),
+            Some("2023.03.04.1".to_string())
+        );
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("unknown"), None);
+    }
+
+    #[test]
+    fn parse_version_reads_ffmpeg_banners() {
+        let parse = |line| Tool::Ffmpeg.parse_version(line);
+        assert_eq!(
+            parse("ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023"),
+            Some("6.1.1".to_string())
+        );
+        assert_eq!(
+            parse("ffmpeg version n7.0 Copyright"),
+            Some("7.0".to_string())
+        );
+        assert_eq!(
+            parse("ffmpeg version 4.4. Copyright"),
+            Some("4.4".to_string())
+        );
+        // Builds from git carry no release number
+        assert_eq!(parse("ffmpeg version N-112345-g0123abcd Copyright"), None);
+        assert_eq!(parse("ffprobe version 6.1"), None);
+    }
+
+    #[test]
+    fn numbers_compare_numerically() {
+        assert!(numbers("2024.08.06") > numbers(Tool::YtDlp.minimum_version()));
+        assert!(numbers("2023.03.03") < numbers(Tool::YtDlp.minimum_version()));
+        assert!(numbers("10.0") > numbers("4.0"));
+        assert!(numbers("4.0.1") > numbers("4.0"));
+        assert!(numbers("3.4.13") < numbers(Tool::Ffmpeg.minimum_version()));
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+// Fields of the info JSON yt-dlp writes next to the download
+#[derive(Debug, Default, Deserialize)]
+pub struct VideoInfo {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    // Length in seconds
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub chapters: Option<Vec<Chapter>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl VideoInfo {
+    // Read `<stem>.info.json` of an MP3 downloaded to `dir` and remove the file
+    pub fn take(dir: &Path, audio_filename: &str) -> Result<VideoInfo, String> {
+        let stem = audio_filename
+            .strip_suffix(".mp3")
+            .unwrap_or(audio_filename);
+        let path = dir.join(format!("{}.info.json", stem));
+        let info = fs::read(&path)
+            .map_err(|e| format!("Cannot read video info {}: {}", path.display(), e))
+            .and_then(|contents| {
+                serde_json::from_slice(&contents)
+                    .map_err(|e| format!("Invalid video info {}: {}", path.display(), e))
+            });
+        let _ = fs::remove_file(&path);
+        info
+    }
+}
@@ -0,0 +1,282 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use serde::Serialize;
+use std::{fs, path::Path as FsPath, process::Stdio};
+use tokio::{io::AsyncReadExt, process::Command};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client_auth::ClientIdentity,
+    config::{Config, DOWNLOAD_DIR},
+    failures::{Failure, FailureKind},
+    tasks::{Artifact, ArtifactKind},
+    AppState,
+};
+
+// Values in a waveform, enough for the width of a player
+const PEAKS: usize = 1000;
+// The audio is decoded as mono at this rate, plenty for an outline
+const SAMPLE_RATE: usize = 8000;
+// Samples per block of the first pass, 20 ms
+const BLOCK: usize = 160;
+
+// Contents of `<stem>.peaks.json`
+#[derive(Serialize)]
+struct Waveform {
+    // Length in seconds of the decoded audio
+    duration: f64,
+    // Loudest absolute sample of each slice of the audio, from 0 to 1
+    peaks: Vec<f32>,
+}
+
+// Decode `<stem>.mp3` in `dir` and write its waveform peaks next to it
+pub async fn generate(
+    config: &Config,
+    dir: &FsPath,
+    audio_filename: &str,
+    abort: &CancellationToken,
+) -> Result<Artifact, Failure> {
+    let mut child = Command::new(&config.ffmpeg_path)
+        .args(["-hide_banner", "-loglevel", "error", "-i"])
+        .arg(dir.join(audio_filename))
+        .args(["-vn", "-ac", "1", "-ar", &SAMPLE_RATE.to_string()])
+        .args(["-f", "s16le", "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Cannot execute ffmpeg: {}", e))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    // Loudest sample of every block, so long files are never held in memory
+    let read_samples = async {
+        let mut blocks: Vec<u16> = Vec::new();
+        let (mut peak, mut count, mut samples) = (0u16, 0usize, 0usize);
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut carry: Option<u8> = None;
+        loop {
+            let read = stdout.read(&mut buffer).await.map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            // A sample may be split between two reads
+            let mut bytes = &buffer[..read];
+            let mut first = None;
+            if let Some(low) = carry.take() {
+                first = Some([low, bytes[0]]);
+                bytes = &bytes[1..];
+            }
+            let chunks = bytes.chunks_exact(2);
+            carry = chunks.remainder().first().copied();
+            for sample in first.into_iter().chain(chunks.map(|c| [c[0], c[1]])) {
+                peak = peak.max(i16::from_le_bytes(sample).unsigned_abs());
+                count += 1;
+                samples += 1;
+                if count == BLOCK {
+                    blocks.push(peak);
+                    (peak, count) = (0, 0);
+                }
+            }
+        }
+        if count > 0 {
+            blocks.push(peak);
+        }
+        Ok::<_, String>((blocks, samples))
+    };
+    // The log is read at the same time, as ffmpeg stops once either pipe is full
+    let read_log = async {
+        let mut log = Vec::new();
+        let _ = stderr.read_to_end(&mut log).await;
+        Ok(log)
+    };
+    let decode = async {
+        let (decoded, log) = tokio::try_join!(read_samples, read_log)?;
+        let status = child.wait().await.map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(String::from_utf8_lossy(&log).trim().to_string());
+        }
+        Ok(decoded)
+    };
+    let (blocks, samples) = tokio::select! {
+        result = decode => result?,
+        _ = abort.cancelled() => return Err(Failure::new(FailureKind::Cancelled)),
+    };
+
+    let waveform = Waveform {
+        duration: samples as f64 / SAMPLE_RATE as f64,
+        peaks: downsample(&blocks),
+    };
+    let stem = audio_filename
+        .strip_suffix(".mp3")
+        .unwrap_or(audio_filename);
+    let filename = format!("{}.peaks.json", stem);
+    let json = serde_json::to_vec(&waveform).map_err(|e| e.to_string())?;
+    fs::write(dir.join(&filename), json)
+        .map_err(|e| format!("Cannot write {}: {}", filename, e))?;
+
+    Ok(Artifact {
+        kind: ArtifactKind::Waveform,
+        filename,
+        title: None,
+        track: None,
+        duration: Some(waveform.duration),
+        language: None,
+    })
+}
+
+// Reduce the blocks to at most `PEAKS` values, keeping the loudest of each slice
+fn downsample(blocks: &[u16]) -> Vec<f32> {
+    let slices = blocks.len().min(PEAKS);
+    (0..slices)
+        .map(|slice| {
+            let start = slice * blocks.len() / slices;
+            let end = ((slice + 1) * blocks.len() / slices).max(start + 1);
+            let peak = blocks[start..end].iter().copied().max().unwrap_or(0);
+            // Three decimals are plenty to draw and keep the JSON small
+            (f32::from(peak) / 32768.0 * 1000.0).round() / 1000.0
+        })
+        .collect()
+}
+
+// GET /waveform/:filename, the peaks of an MP3 of a task
+pub async fn peaks(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(filename): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let stem = filename.strip_suffix(".mp3").ok_or(StatusCode::NOT_FOUND)?;
+    let peaks = format!("{}.peaks.json", stem);
+    if !state.tasks.file_in_use(&peaks)
+        || !identity.may_access(state.tasks.owner_of_file(&peaks).as_deref())
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let contents =
+        fs::read(FsPath::new(DOWNLOAD_DIR).join(&peaks)).map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], contents))
+}
+
+// GET /player.js, shared by the converter and the history page
+pub async fn player_script() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        PLAYER_SCRIPT,
+    )
+}
+
+// `createPlayer(container, tracks)` renders an audio element streaming from
+// `/stream/`, with the waveform of the current track drawn on a canvas that
+// shows the progress and seeks on click. `tracks` holds `{filename, title}`;
+// a selector is shown when there are several.
+const PLAYER_SCRIPT: &str = r#"
+function createPlayer(container, tracks) {
+    const player = document.createElement('div');
+    player.className = 'player';
+    player.style.cssText = 'margin: 10px 0; width: 100%;';
+
+    const select = document.createElement('select');
+    select.style.cssText = 'margin-bottom: 6px; max-width: 100%;';
+    tracks.forEach((track, index) => {
+        const option = document.createElement('option');
+        option.value = index;
+        option.textContent = track.title || track.filename;
+        select.append(option);
+    });
+
+    const canvas = document.createElement('canvas');
+    canvas.style.cssText = 'display: block; width: 100%; height: 64px; cursor: pointer; background: #f3f3f3; border-radius: 6px;';
+
+    const audio = document.createElement('audio');
+    audio.controls = true;
+    audio.preload = 'metadata';
+    audio.style.cssText = 'display: block; width: 100%; margin-top: 6px;';
+
+    let peaks = [];
+    let scale = 1;
+
+    function draw() {
+        const ratio = window.devicePixelRatio || 1;
+        const width = canvas.clientWidth || 300;
+        const height = 64;
+        canvas.width = width * ratio;
+        canvas.height = height * ratio;
+        const context = canvas.getContext('2d');
+        context.setTransform(ratio, 0, 0, ratio, 0, 0);
+        context.clearRect(0, 0, width, height);
+        if (peaks.length === 0) return;
+        const progress = audio.duration ? audio.currentTime / audio.duration : 0;
+        const middle = height / 2;
+        for (let x = 0; x < width; x++) {
+            const from = Math.floor(x * peaks.length / width);
+            const to = Math.max(from + 1, Math.floor((x + 1) * peaks.length / width));
+            const peak = Math.max(...peaks.slice(from, to)) * scale;
+            const bar = Math.max(1, peak * (middle - 2));
+            context.fillStyle = x / width < progress ? '#764ba2' : '#b8bde6';
+            context.fillRect(x, middle - bar, 1, bar * 2);
+        }
+    }
+
+    function load(index) {
+        const track = tracks[index];
+        peaks = [];
+        draw();
+        audio.src = `/stream/${encodeURIComponent(track.filename)}`;
+        fetch(`/waveform/${encodeURIComponent(track.filename)}`)
+            .then((response) => response.ok ? response.json() : { peaks: [] })
+            .then((waveform) => {
+                peaks = waveform.peaks || [];
+                const loudest = Math.max(0, ...peaks);
+                scale = loudest > 0 ? 1 / loudest : 1;
+                draw();
+            })
+            .catch(() => {});
+    }
+
+    canvas.addEventListener('click', (event) => {
+        if (!audio.duration) return;
+        const bounds = canvas.getBoundingClientRect();
+        audio.currentTime = (event.clientX - bounds.left) / bounds.width * audio.duration;
+        audio.play();
+    });
+    audio.addEventListener('timeupdate', draw);
+    audio.addEventListener('seeked', draw);
+    select.addEventListener('change', () => load(select.value));
+    window.addEventListener('resize', draw);
+
+    if (tracks.length > 1) player.append(select);
+    player.append(canvas, audio);
+    container.append(player);
+    load(0);
+    return player;
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample_keeps_short_input() {
+        assert_eq!(downsample(&[0, 16384, 32767]), [0.0, 0.5, 1.0]);
+        assert!(downsample(&[]).is_empty());
+    }
+
+    #[test]
+    fn downsample_keeps_the_loudest_block_of_each_slice() {
+        let mut blocks = vec![0u16; PEAKS * 3 + 1];
+        blocks[4] = 8192;
+        blocks[PEAKS * 3] = 32767;
+        let peaks = downsample(&blocks);
+        assert_eq!(peaks.len(), PEAKS);
+        assert_eq!(peaks[1], 0.25);
+        assert_eq!(peaks[PEAKS - 1], 1.0);
+        assert_eq!(peaks.iter().filter(|&&peak| peak > 0.0).count(), 2);
+    }
+}
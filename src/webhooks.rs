@@ -0,0 +1,371 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{
+    client_auth::ClientIdentity,
+    config::Config,
+    history::url_path_segment,
+    shutdown::Shutdown,
+    tasks::{unix_now, DeliveryAttempt, TaskRecord, TaskStatus, TaskStore},
+    AppState,
+};
+
+// Delay before the first retry, doubled after every failed attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+// Notifies callback URLs and the configured webhooks when tasks finish
+pub struct Webhooks {
+    client: reqwest::Client,
+    config: Arc<Config>,
+    tasks: Arc<TaskStore>,
+    shutdown: Shutdown,
+}
+
+impl Webhooks {
+    pub fn new(config: Arc<Config>, tasks: Arc<TaskStore>, shutdown: Shutdown) -> Self {
+        let client = client_builder(&config)
+            .build()
+            .expect("Failed to build webhook HTTP client");
+        Webhooks {
+            client,
+            config,
+            tasks,
+            shutdown,
+        }
+    }
+
+    // Send the task's final state to its callback URL and the global webhooks
+    //
+    // Deliveries run in the background, tracked so shutdown waits for them;
+    // returns how many were started, none while the task is still processing.
+    pub fn notify(self: &Arc<Self>, task_id: &str) -> usize {
+        let Some(record) = self.tasks.get(task_id) else {
+            return 0;
+        };
+        let Some((event, body)) = payload(task_id, &record, &self.config) else {
+            return 0;
+        };
+
+        let targets: Vec<String> = record
+            .callback_url
+            .into_iter()
+            .chain(self.config.webhook_urls.iter().cloned())
+            .collect();
+        for url in &targets {
+            let webhooks = self.clone();
+            let (task_id, url, body) = (task_id.to_string(), url.clone(), body.clone());
+            self.shutdown
+                .spawn_delivery(async move { webhooks.deliver(task_id, url, event, body).await });
+        }
+        targets.len()
+    }
+
+    // POST the payload, retrying with exponential backoff until it is accepted
+    //
+    // Once shutdown aborts, the attempt in flight is finished but no retry is made.
+    async fn deliver(&self, task_id: String, url: String, event: &'static str, body: String) {
+        let delivery_id = Uuid::new_v4().to_string();
+        let max_attempts = self.config.webhook_max_attempts;
+
+        for attempt in 1..=max_attempts {
+            let timestamp = unix_now();
+            let response = match self.client_for(&url).await {
+                Ok(client) => {
+                    let mut request = client
+                        .post(&url)
+                        .header(CONTENT_TYPE, "application/json")
+                        .header("X-YT-MP3-Event", event)
+                        .header("X-YT-MP3-Delivery", &delivery_id)
+                        .header("X-YT-MP3-Timestamp", timestamp.to_string())
+                        .body(body.clone());
+                    if let Some(secret) = &self.config.webhook_secret {
+                        request = request
+                            .header("X-YT-MP3-Signature", signature(secret, timestamp, &body));
+                    }
+                    request.send().await.map_err(|e| e.to_string())
+                }
+                Err(error) => Err(error),
+            };
+
+            let (status_code, error) = match response {
+                Ok(response) if response.status().is_success() => {
+                    (Some(response.status().as_u16()), None)
+                }
+                Ok(response) => (
+                    Some(response.status().as_u16()),
+                    Some(format!("HTTP {}", response.status())),
+                ),
+                Err(error) => (None, Some(error)),
+            };
+            let delivered = error.is_none();
+            self.tasks.record_delivery(
+                &task_id,
+                DeliveryAttempt {
+                    delivery_id: delivery_id.clone(),
+                    url: url.clone(),
+                    event: event.to_string(),
+                    attempt,
+                    at: timestamp,
+                    status_code,
+                    error,
+                    delivered,
+                },
+            );
+            if delivered {
+                return;
+            }
+
+            if attempt < max_attempts {
+                let abort = self.shutdown.abort_token();
+                tokio::select! {
+                    _ = tokio::time::sleep(retry_delay(attempt)) => {}
+                    _ = abort.cancelled() => {
+                        eprintln!(
+                            "⚠️  Webhook to {} for task {} given up at shutdown after {} attempt(s)",
+                            url, task_id, attempt
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+        eprintln!(
+            "⚠️  Webhook to {} for task {} failed after {} attempts",
+            url, task_id, max_attempts
+        );
+    }
+
+    // HTTP client for one attempt; callback URLs come from users, so they are
+    // checked again on every attempt and the connection is pinned to the
+    // checked addresses, leaving no room for a DNS change in between
+    async fn client_for(&self, url: &str) -> Result<reqwest::Client, String> {
+        if self
+            .config
+            .webhook_urls
+            .iter()
+            .any(|configured| configured == url)
+        {
+            return Ok(self.client.clone());
+        }
+        let addrs = check_callback_url(url, &self.config).await?;
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|parsed| parsed.host_str().map(str::to_string))
+            .unwrap_or_default();
+        client_builder(&self.config)
+            .resolve_to_addrs(&host, &addrs)
+            .build()
+            .map_err(|e| e.to_string())
+    }
+}
+
+// Wait after failed attempt `attempt`: 5 s, 10 s, 20 s, …, doubling at most
+// 20 times so large attempt counts cannot overflow
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY.saturating_mul(1 << attempt.saturating_sub(1).min(20))
+}
+
+// Redirects are not followed: they would turn the POST into a GET and could
+// lead past the address checks
+fn client_builder(config: &Config) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.webhook_timeout_secs))
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("yt-mp3-service/", env!("CARGO_PKG_VERSION")))
+}
+
+// Resolve a callback URL and refuse hosts on the server's own networks, so
+// callbacks cannot probe loopback services, the LAN or cloud metadata
+// endpoints; hosts in `YT_MP3_WEBHOOK_ALLOWED_HOSTS` are exempt
+//
+// Returns the resolved addresses the delivery must connect to.
+pub async fn check_callback_url(url: &str, config: &Config) -> Result<Vec<SocketAddr>, String> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|_| "callback_url is not a valid URL".to_string())?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| "callback_url has no host".to_string())?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| format!("callback_url host {} cannot be resolved", host))?
+        .collect();
+
+    let allowed = config.webhook_allowed_hosts.iter().any(|allowed| {
+        allowed.eq_ignore_ascii_case(host)
+            || addrs.iter().any(|addr| allowed.parse() == Ok(addr.ip()))
+    });
+    if !allowed && addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(
+            "callback_url must not point to a loopback, private or link-local address".to_string(),
+        );
+    }
+    Ok(addrs)
+}
+
+// Whether an address is on the public internet, excluding loopback, private,
+// link-local (cloud metadata at 169.254.169.254), carrier-grade NAT,
+// unique-local and multicast ranges
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [first, second, ..] = v4.octets();
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || first == 0
+                || (first == 100 && (second & 0xc0) == 64))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+// Event name and JSON body for a finished task
+//
+// The download URL starts with `YT_MP3_PUBLIC_URL`, or the scheme and host the
+// task was submitted through; tasks of earlier versions have none.
+fn payload(task_id: &str, record: &TaskRecord, config: &Config) -> Option<(&'static str, String)> {
+    let (event, mut body) = match &record.status {
+        TaskStatus::Processing => return None,
+        TaskStatus::Completed(filename, thumbnail) => (
+            "task.completed",
+            json!({
+                "status": "completed",
+                "filename": filename,
+                "download_url": config
+                    .public_url
+                    .as_deref()
+                    .or(record.base_url.as_deref())
+                    .map(|base| format!("{}/download/{}", base, url_path_segment(filename))),
+                "thumbnail": thumbnail,
+            }),
+        ),
+        TaskStatus::Failed(error) => (
+            "task.failed",
+            json!({
+                "status": "failed",
+                "error": error,
+                "error_code": record.error_code,
+                "retryable": record.error_code.is_some_and(|code| code.retryable()),
+            }),
+        ),
+    };
+    body["event"] = json!(event);
+    body["task_id"] = json!(task_id);
+    body["timestamp"] = json!(unix_now());
+    Some((event, body.to_string()))
+}
+
+// `sha256=<hex>` HMAC of `<timestamp>.<body>`; the timestamp is signed so
+// receivers can reject replayed requests
+fn signature(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+// Webhook delivery history of a task
+pub async fn history(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(task_id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let record = state
+        .tasks
+        .get(&task_id)
+        .filter(|record| identity.may_access(record.owner.as_deref()))
+        .ok_or_else(task_not_found)?;
+    Ok(Json(json!({
+        "task_id": task_id,
+        "callback_url": record.callback_url,
+        "deliveries": record.deliveries,
+    })))
+}
+
+// Deliver a finished task's notification again
+pub async fn replay(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(task_id): Path<String>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let record = state
+        .tasks
+        .get(&task_id)
+        .filter(|record| identity.may_access(record.owner.as_deref()))
+        .ok_or_else(task_not_found)?;
+    if matches!(record.status, TaskStatus::Processing) {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "Task is still processing" })),
+        ));
+    }
+
+    let deliveries = state.webhooks.notify(&task_id);
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "task_id": task_id,
+            "deliveries": deliveries,
+        })),
+    ))
+}
+
+fn task_not_found() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "status": "not_found",
+            "error": "Task not found"
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_without_overflowing() {
+        assert_eq!(retry_delay(1), Duration::from_secs(5));
+        assert_eq!(retry_delay(2), Duration::from_secs(10));
+        assert_eq!(retry_delay(4), Duration::from_secs(40));
+        assert_eq!(retry_delay(21), retry_delay(40));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(5 << 20));
+    }
+}